@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+
+use bytesize::ByteSize;
+
+use crate::{Compression, Config, Encoding, ExecMode, Language};
+
+/// Builder of [`Config`] starting from the command line defaults
+#[derive(Debug, Default)]
+pub struct Builder {
+    config: Config,
+}
+
+impl Builder {
+    pub fn manifest_path(mut self, manifest_path: impl Into<PathBuf>) -> Self {
+        self.config.manifest_path = Some(manifest_path.into());
+        self
+    }
+
+    pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.config.output = Some(output.into());
+        self
+    }
+
+    pub fn all_bins(mut self, all_bins: bool) -> Self {
+        self.config.all_bins = all_bins;
+        self
+    }
+
+    pub fn output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+        self.config.output_dir = output_dir.into();
+        self
+    }
+
+    pub fn output_pattern(mut self, output_pattern: impl Into<String>) -> Self {
+        self.config.output_pattern = output_pattern.into();
+        self
+    }
+
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.config.package = Some(package.into());
+        self
+    }
+
+    pub fn bin(mut self, bin: impl Into<String>) -> Self {
+        self.config.bin = Some(bin.into());
+        self
+    }
+
+    pub fn example(mut self, example: impl Into<String>) -> Self {
+        self.config.example = Some(example.into());
+        self
+    }
+
+    pub fn input(mut self, input: impl Into<PathBuf>) -> Self {
+        self.config.input = Some(input.into());
+        self
+    }
+
+    pub fn source(mut self, source: impl Into<PathBuf>) -> Self {
+        self.config.source = Some(source.into());
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.config.target = Some(target.into());
+        self
+    }
+
+    pub fn judge(mut self, judge: impl Into<String>) -> Self {
+        self.config.judge = Some(judge.into());
+        self
+    }
+
+    pub fn judge_file(mut self, judge_file: impl Into<PathBuf>) -> Self {
+        self.config.judge_file = Some(judge_file.into());
+        self
+    }
+
+    pub fn use_cross(mut self, use_cross: bool) -> Self {
+        self.config.use_cross = use_cross;
+        self
+    }
+
+    pub fn panic_unwind(mut self, panic_unwind: bool) -> Self {
+        self.config.panic_unwind = panic_unwind;
+        self
+    }
+
+    pub fn no_opt_size(mut self, no_opt_size: bool) -> Self {
+        self.config.no_opt_size = no_opt_size;
+        self
+    }
+
+    pub fn feature(mut self, feature: impl Into<String>) -> Self {
+        self.config.features.push(feature.into());
+        self
+    }
+
+    pub fn all_features(mut self, all_features: bool) -> Self {
+        self.config.all_features = all_features;
+        self
+    }
+
+    pub fn no_default_features(mut self, no_default_features: bool) -> Self {
+        self.config.no_default_features = no_default_features;
+        self
+    }
+
+    pub fn rustflags(mut self, rustflags: impl Into<String>) -> Self {
+        self.config.rustflags.push(rustflags.into());
+        self
+    }
+
+    pub fn toolchain(mut self, toolchain: impl Into<String>) -> Self {
+        self.config.toolchain = Some(toolchain.into());
+        self
+    }
+
+    pub fn no_build_std(mut self, no_build_std: bool) -> Self {
+        self.config.no_build_std = no_build_std;
+        self
+    }
+
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.config.profile = Some(profile.into());
+        self
+    }
+
+    pub fn keep_profile(mut self, keep_profile: bool) -> Self {
+        self.config.keep_profile = keep_profile;
+        self
+    }
+
+    pub fn profile_override(mut self, setting: impl Into<String>) -> Self {
+        self.config.profile_override.push(setting.into());
+        self
+    }
+
+    pub fn no_upx(mut self, no_upx: bool) -> Self {
+        self.config.no_upx = no_upx;
+        self
+    }
+
+    pub fn language(mut self, language: Language) -> Self {
+        self.config.language = Some(language);
+        self
+    }
+
+    pub fn exec_mode(mut self, exec_mode: ExecMode) -> Self {
+        self.config.exec_mode = Some(exec_mode);
+        self
+    }
+
+    pub fn use_exec(mut self, use_exec: bool) -> Self {
+        self.config.use_exec = use_exec;
+        self
+    }
+
+    pub fn check_hash(mut self, check_hash: bool) -> Self {
+        self.config.check_hash = check_hash;
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.config.compression = Some(compression);
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.config.encoding = Some(encoding);
+        self
+    }
+
+    pub fn max_size(mut self, max_size: ByteSize) -> Self {
+        self.config.max_size = Some(max_size);
+        self
+    }
+
+    pub fn fit(mut self, fit: bool) -> Self {
+        self.config.fit = fit;
+        self
+    }
+
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.config.verify = verify;
+        self
+    }
+
+    pub fn verify_input(mut self, verify_input: impl Into<PathBuf>) -> Self {
+        self.config.verify_input.push(verify_input.into());
+        self
+    }
+
+    pub fn verify_edition(mut self, verify_edition: impl Into<String>) -> Self {
+        self.config.verify_edition = Some(verify_edition.into());
+        self
+    }
+
+    pub fn watch(mut self, watch: bool) -> Self {
+        self.config.watch = watch;
+        self
+    }
+
+    pub fn cargo_arg(mut self, arg: impl Into<String>) -> Self {
+        self.config.cargo_args.push(arg.into());
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
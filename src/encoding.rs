@@ -0,0 +1,173 @@
+//! Text encodings of the embedded binary that are denser than Base64.
+//!
+//! The alphabets avoid `"`, `\` and `?` so that the encoded text can be pasted into Rust and
+//! Python byte string literals and C++ string literals (no trigraphs) without escaping.
+
+/// RFC 1924 alphabet with `?` replaced by `.`
+pub const BASE85: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>.@^_`{|}~";
+
+/// Printable ASCII without space, `"`, `\` and `?`
+pub const BASE91: &[u8; 91] =
+    b"!#$%&'()*+,-./0123456789:;<=>@ABCDEFGHIJKLMNOPQRSTUVWXYZ[]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Encodes 4-byte big-endian groups into 5 digits; a trailing group of `n` bytes is encoded
+/// into `n + 1` digits, as in Python's `base64.b85encode`.
+pub fn base85(data: &[u8]) -> String {
+    let mut out = Vec::with_capacity(data.len().div_ceil(4) * 5);
+    for chunk in data.chunks(4) {
+        let mut group = [0; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut x = u32::from_be_bytes(group);
+        let mut digits = [0; 5];
+        for d in digits.iter_mut().rev() {
+            *d = BASE85[(x % 85) as usize];
+            x /= 85;
+        }
+        out.extend_from_slice(&digits[..chunk.len() + 1]);
+    }
+    String::from_utf8(out).expect("alphabet should be ASCII")
+}
+
+/// basE91 by Joachim Henke, using [`BASE91`] as the alphabet.
+pub fn base91(data: &[u8]) -> String {
+    let mut out = Vec::with_capacity(data.len() * 16 / 13 + 2);
+    let (mut x, mut n) = (0u32, 0);
+    for &byte in data {
+        x |= (byte as u32) << n;
+        n += 8;
+        if n > 13 {
+            let mut v = x & 8191;
+            if v > 88 {
+                x >>= 13;
+                n -= 13;
+            } else {
+                v = x & 16383;
+                x >>= 14;
+                n -= 14;
+            }
+            out.push(BASE91[(v % 91) as usize]);
+            out.push(BASE91[(v / 91) as usize]);
+        }
+    }
+    if n > 0 {
+        out.push(BASE91[(x % 91) as usize]);
+        if n > 7 || x > 90 {
+            out.push(BASE91[(x / 91) as usize]);
+        }
+    }
+    String::from_utf8(out).expect("alphabet should be ASCII")
+}
+
+/// Inverse of [`base85`], or `None` on a character outside [`BASE85`]
+pub fn decode_base85(text: &[u8]) -> Option<Vec<u8>> {
+    let mut table = [None; 256];
+    for (i, &c) in BASE85.iter().enumerate() {
+        table[c as usize] = Some(i as u64);
+    }
+    let mut out = Vec::with_capacity(text.len() / 5 * 4 + 4);
+    for chunk in text.chunks(5) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        // a trailing group is padded with the last digit
+        let mut x = 0;
+        for i in 0..5 {
+            x = x * 85 + chunk.get(i).map_or(Some(84), |&c| table[c as usize])?;
+        }
+        let group = u32::try_from(x).ok()?.to_be_bytes();
+        out.extend_from_slice(&group[..chunk.len() - 1]);
+    }
+    Some(out)
+}
+
+/// Inverse of [`base91`], or `None` on a character outside [`BASE91`]
+pub fn decode_base91(text: &[u8]) -> Option<Vec<u8>> {
+    let mut table = [None; 256];
+    for (i, &c) in BASE91.iter().enumerate() {
+        table[c as usize] = Some(i as u32);
+    }
+    let mut out = Vec::with_capacity(text.len() * 13 / 16 + 1);
+    let (mut x, mut n, mut v) = (0u32, 0, None);
+    for &c in text {
+        let d = table[c as usize]?;
+        match v.take() {
+            None => v = Some(d),
+            Some(w) => {
+                let w = w + d * 91;
+                x |= w << n;
+                n += if w & 8191 > 88 { 13 } else { 14 };
+                while n > 7 {
+                    out.push(x as u8);
+                    x >>= 8;
+                    n -= 8;
+                }
+            }
+        }
+    }
+    if let Some(w) = v {
+        out.push((x | w << n) as u8);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs of every trailing chunk length, with the extreme byte values
+    fn inputs() -> impl Iterator<Item = Vec<u8>> {
+        (0..64).flat_map(|len| {
+            [
+                vec![0; len],
+                vec![255; len],
+                (0..len).map(|i| (i * 167 + 13) as u8).collect(),
+            ]
+        })
+    }
+
+    fn check_alphabet(text: &str) {
+        assert!(
+            !text.contains(['"', '\\', '?']),
+            "`{text}` needs escaping in a literal"
+        );
+    }
+
+    #[test]
+    fn base85_round_trip() {
+        for data in inputs() {
+            let text = base85(&data);
+            check_alphabet(&text);
+            assert_eq!(
+                text.len(),
+                data.len() / 4 * 5 + data.len() % 4 + (data.len() % 4 > 0) as usize
+            );
+            assert_eq!(decode_base85(text.as_bytes()), Some(data));
+        }
+    }
+
+    #[test]
+    fn base85_matches_python() {
+        assert_eq!(base85(b"hello"), "Xk~0{Zv");
+        assert_eq!(base85(&[250, 251, 252, 253, 254, 255]), "`uqI-{{H");
+    }
+
+    #[test]
+    fn base91_round_trip() {
+        for data in inputs() {
+            let text = base91(&data);
+            check_alphabet(&text);
+            assert_eq!(decode_base91(text.as_bytes()), Some(data));
+        }
+    }
+
+    #[test]
+    fn invalid_characters() {
+        assert_eq!(decode_base85(b"Xk?0{"), None);
+        assert_eq!(decode_base85(b"Xk~0{"), Some(b"hell".to_vec()));
+        // a single trailing digit does not encode any byte
+        assert_eq!(decode_base85(b"Xk~0{Z"), None);
+        assert_eq!(decode_base91(b"ab\"c"), None);
+        assert_eq!(decode_base91(b"ab c"), None);
+    }
+}
@@ -0,0 +1,192 @@
+//! Recovery of the executable and the source code from generated code, for `extract`.
+//!
+//! The parts are located with the same templates that generate the code, so only code generated
+//! by this version of the tool is recognized.
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{anyhow, ensure, Context as _, Result};
+use data_encoding::{BASE64, BASE64_NOPAD, HEXUPPER};
+use sha2::digest::Digest;
+use structopt::StructOpt;
+
+use crate::{encoding, lz4, Compression, Encoding, Language};
+
+#[derive(Debug, Clone, PartialEq, StructOpt)]
+pub struct Extract {
+    /// Code generated by binary-source
+    #[structopt(value_name("PATH"))]
+    pub bundle: PathBuf,
+
+    /// Output path of the executable [default: the name embedded in the code]
+    #[structopt(long, value_name("PATH"))]
+    pub binary: Option<PathBuf>,
+
+    /// Output path of the source code, needed for sources embedded by `--input --source` in
+    /// other languages than Rust [default: <BUNDLE>.src.rs]
+    #[structopt(long, value_name("PATH"))]
+    pub source: Option<PathBuf>,
+}
+
+/// Parts of generated code
+#[derive(Debug, Clone)]
+pub struct Bundle<'a> {
+    pub language: Language,
+    pub compression: Compression,
+    pub encoding: Encoding,
+    /// Source code in the comment block
+    pub source: &'a str,
+    /// File name of the executable, `bin<HASH>` with `.exe` for Windows
+    pub name: &'a str,
+    /// Decoded and decompressed executable
+    pub binary: Vec<u8>,
+}
+
+/// Text between `prefix` and the next `"` in `code`
+fn quoted<'a>(code: &'a str, prefix: &str) -> Option<&'a str> {
+    let start = code.find(prefix)? + prefix.len();
+    let len = code[start..].find('"')?;
+    Some(&code[start..start + len])
+}
+
+impl<'a> Bundle<'a> {
+    /// Splits `code` into its parts, and checks the executable against the hash in its name
+    pub fn parse(code: &'a str) -> Result<Self> {
+        let (language, source) =
+            Language::split_bundle(code).with_context(|| "Not generated by binary-source")?;
+        let runner = language.runner();
+        // `source` borrows from `code`
+        let rest = &code[source.as_ptr() as usize - code.as_ptr() as usize + source.len()..];
+
+        // the statement that declares the binary starts after the last block or line
+        let (before, _) = runner.template.split_once("{{BINARY}}").unwrap();
+        let payload = quoted(rest, before.rsplit(['}', '\n']).next().unwrap())
+            .with_context(|| "Failed to find the embedded binary")?;
+        let name = [runner.exec_tempfile, runner.cache_lookup]
+            .into_iter()
+            .find_map(|execute| quoted(rest, execute.split_once("{{NAME}}").unwrap().0))
+            .with_context(|| "Failed to find the file name")?;
+
+        let encoding = [Encoding::Base85, Encoding::Base91]
+            .into_iter()
+            .zip([runner.decode_base85, runner.decode_base91])
+            .find(|(_, decode)| rest.contains(decode))
+            .map_or(Encoding::Base64, |(encoding, _)| encoding);
+        let compression = if rest.contains(runner.decompress_lz4) {
+            Compression::Lz4
+        } else {
+            Compression::None
+        };
+
+        let decoded = match encoding {
+            Encoding::Base64 => match language {
+                Language::Rust | Language::Cpp => BASE64_NOPAD.decode(payload.as_bytes()).ok(),
+                Language::Python => BASE64.decode(payload.as_bytes()).ok(),
+            },
+            Encoding::Base85 => encoding::decode_base85(payload.as_bytes()),
+            Encoding::Base91 => encoding::decode_base91(payload.as_bytes()),
+        }
+        .with_context(|| format!("Failed to decode the embedded binary as {encoding:?}"))?;
+        let binary = match compression {
+            Compression::None => decoded,
+            Compression::Lz4 => lz4::decompress(&decoded)
+                .with_context(|| "Failed to decompress the embedded binary")?,
+        };
+
+        let hash = name
+            .strip_prefix("bin")
+            .map(|hash| hash.trim_end_matches(".exe"))
+            .filter(|hash| !hash.is_empty())
+            .ok_or_else(|| anyhow!("File name `{name}` does not contain a hash"))?;
+        let actual = HEXUPPER.encode(&sha2::Sha256::digest(&binary));
+        ensure!(
+            actual.starts_with(hash),
+            "Hash mismatch of the embedded binary: `{hash}` in the name, but `{actual}`"
+        );
+
+        Ok(Self {
+            language,
+            compression,
+            encoding,
+            source,
+            name,
+            binary,
+        })
+    }
+}
+
+impl Extract {
+    /// Writes the executable and the source code of `bundle` out separately
+    pub fn run(&self) -> Result<()> {
+        let code = fs::read_to_string(&self.bundle)
+            .with_context(|| format!("Failed to read `{}`", self.bundle.display()))?;
+        let bundle = Bundle::parse(&code)
+            .with_context(|| format!("Failed to extract `{}`", self.bundle.display()))?;
+        println!(
+            "Found {:?} code with {:?} compression and {:?} encoding",
+            bundle.language, bundle.compression, bundle.encoding
+        );
+
+        let binary = self.binary.clone().unwrap_or(bundle.name.into());
+        fs::write(&binary, &bundle.binary)
+            .with_context(|| format!("Failed to write `{}`", binary.display()))?;
+        #[cfg(unix)]
+        fs::set_permissions(&binary, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+        println!("Wrote binary to `{}`", binary.display());
+
+        let source = match &self.source {
+            Some(source) => source.clone(),
+            // the bundle does not record the file name of the source, which is Rust unless it
+            // was given by `--source`
+            None => self.bundle.with_extension("src.rs"),
+        };
+        fs::write(&source, format!("{}\n", bundle.source))
+            .with_context(|| format!("Failed to write `{}`", source.display()))?;
+        println!("Wrote source to `{}`", source.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::TestTarget, Config, ExecMode};
+
+    #[test]
+    fn parse_embedded() {
+        let source = "fn main() {\n    println!(\"\\\"{{BINARY}}\\\"\");\n}";
+        let binary: Vec<u8> = (0..5000u32).map(|i| (i * i % 251 / 7) as u8).collect();
+        let target = TestTarget::new(&binary, Some(source));
+        let ctx = target.ctx();
+
+        for language in [Language::Rust, Language::Python, Language::Cpp] {
+            for compression in [Compression::None, Compression::Lz4] {
+                for encoding in [Encoding::Base64, Encoding::Base85, Encoding::Base91] {
+                    for exec_mode in [ExecMode::TempFile, ExecMode::Memfd, ExecMode::Cache] {
+                        let config = Config {
+                            language: Some(language),
+                            compression: Some(compression),
+                            encoding: Some(encoding),
+                            exec_mode: Some(exec_mode),
+                            ..Config::default()
+                        };
+                        let code = config.embed(&ctx).unwrap();
+                        let bundle = Bundle::parse(&code).unwrap();
+                        assert_eq!(bundle.language, language);
+                        assert_eq!(bundle.compression, compression);
+                        assert_eq!(bundle.encoding, encoding);
+                        assert_eq!(bundle.source, source);
+                        assert_eq!(bundle.binary, binary);
+                        let hash = HEXUPPER.encode(&sha2::Sha256::digest(&binary));
+                        assert_eq!(bundle.name, format!("bin{hash}"));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reject_other_code() {
+        assert!(Bundle::parse("fn main() {}\n").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! Presets of the target and limits for online judges, selected with `--judge`.
+
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context as _, Result};
+use bytesize::ByteSize;
+use serde::{Deserialize, Deserializer};
+
+use crate::Language;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Judge {
+    /// Target triple
+    pub target: Option<String>,
+    /// Passed as `-C target-cpu`
+    pub target_cpu: Option<String>,
+    /// Passed as `-C target-feature`, e.g. `["+avx2", "+bmi2"]`
+    #[serde(default)]
+    pub target_features: Vec<String>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub language: Option<Language>,
+    /// Source size limit of the judge
+    #[serde(default, deserialize_with = "from_str")]
+    pub max_size: Option<ByteSize>,
+}
+
+pub(crate) fn from_str<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+/// `$XDG_CONFIG_HOME/binary-source/judges.toml` or `~/.config/binary-source/judges.toml`
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("binary-source").join("judges.toml"))
+}
+
+fn parse(src: &str, path: &Path) -> Result<BTreeMap<String, Judge>> {
+    toml::from_str(src).with_context(|| format!("Failed to parse `{}`", path.display()))
+}
+
+impl Judge {
+    /// Finds the preset `name` in `path` (or the default path), then in the built-in presets.
+    pub fn load(name: &str, path: Option<&Path>) -> Result<Self> {
+        let mut judges = parse(
+            include_str!("../data/judges.toml"),
+            Path::new("data/judges.toml"),
+        )?;
+        let path = path.map(Path::to_path_buf).or_else(default_path);
+        if let Some(path) = path.filter(|path| path.is_file()) {
+            let src = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read `{}`", path.display()))?;
+            judges.extend(parse(&src, &path)?);
+        }
+        let names = judges.keys().cloned().collect::<Vec<_>>().join(", ");
+        judges
+            .remove(name)
+            .with_context(|| format!("Failed to find judge `{name}` in: {names}"))
+    }
+
+    /// `RUSTFLAGS` for the CPU of the judge
+    pub fn rustflags(&self) -> Vec<String> {
+        let mut rustflags = vec![];
+        if let Some(target_cpu) = &self.target_cpu {
+            rustflags.push(format!("-C target-cpu={target_cpu}"));
+        }
+        if !self.target_features.is_empty() {
+            rustflags.push(format!(
+                "-C target-feature={}",
+                self.target_features.join(",")
+            ));
+        }
+        rustflags
+    }
+}
@@ -0,0 +1,1776 @@
+//! Generates source code with embedded Rust executable binaries.
+//!
+//! The pipeline is driven by [`Config`], which is either parsed from the command line or
+//! assembled with [`Config::builder`], and then completed for each target by
+//! [`Config::with_project`] with the [`Project`] defaults and the [`Judge`] preset. Each stage is
+//! exposed separately so that it can be reused from build scripts and test harnesses.
+//!
+//! ```no_run
+//! use binary_source::{Config, Language};
+//!
+//! Config::builder()
+//!     .bin("a")
+//!     .language(Language::Python)
+//!     .output("a.py")
+//!     .build()
+//!     .run()?;
+//! # anyhow::Ok(())
+//! ```
+
+mod builder;
+mod encoding;
+mod extract;
+mod judge;
+mod lz4;
+mod project;
+mod rustflags;
+mod watch;
+
+pub use builder::Builder;
+pub use extract::{Bundle, Extract};
+pub use judge::Judge;
+pub use project::Project;
+
+use std::{
+    env::{self, current_dir, temp_dir},
+    ffi::OsString,
+    fs,
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    slice,
+    str::FromStr,
+    thread,
+};
+
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
+use bytesize::ByteSize;
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    Message, Metadata, MetadataCommand, Package, Target,
+};
+use data_encoding::{BASE64, BASE64_NOPAD, HEXUPPER};
+use sha2::digest::Digest;
+use structopt::StructOpt;
+
+fn get_file_size(path: impl AsRef<Path>) -> Result<u64> {
+    Ok(fs::metadata(path)?.len())
+}
+
+/// Runs `rustc` and returns its stdout on success
+fn rustc(toolchain: Option<&str>, args: &[&str]) -> Option<String> {
+    let mut cmd = Command::new("rustc");
+    if let Some(toolchain) = toolchain {
+        cmd.arg(format!("+{toolchain}"));
+    }
+    let output = cmd.args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn write_code(path: &Path, src: &[u8]) -> Result<()> {
+    fs::write(path, src).with_context(|| format!("Failed to write `{}`", path.display()))?;
+    println!("Wrote code to `{}`", path.display());
+    Ok(())
+}
+
+/// Runs `argv` with `input` as stdin, and returns its exit code and stdout, where termination by
+/// signal `N` counts as `128 + N` as the Rust runner exits with it
+fn run_with_input(argv: &[OsString], input: &[u8]) -> Result<(Option<i32>, Vec<u8>)> {
+    let mut child = Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run `{}`", argv[0].to_string_lossy()))?;
+    let mut stdin = child.stdin.take().expect("stdin should be piped");
+    let output = thread::scope(|s| {
+        // the process may exit without reading all of its input
+        s.spawn(move || stdin.write_all(input));
+        child.wait_with_output()
+    })?;
+    #[cfg(unix)]
+    let code = output
+        .status
+        .code()
+        .or_else(|| std::os::unix::process::ExitStatusExt::signal(&output.status).map(|n| 128 + n));
+    #[cfg(not(unix))]
+    let code = output.status.code();
+    Ok((code, output.stdout))
+}
+
+fn utf8_path(path: &Path) -> Result<&Utf8Path> {
+    Utf8Path::from_path(path).with_context(|| format!("`{}` is not UTF-8", path.display()))
+}
+
+/// Creates an empty file for `name` in the temp directory, with a prefix unique to this run
+fn create_temp_file(name: &str) -> Result<Utf8PathBuf> {
+    create_temp(name, |path| {
+        fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map(drop)
+    })
+}
+
+/// Creates an empty directory for `name` in the temp directory, with a prefix unique to this run
+fn create_temp_dir(name: &str) -> Result<Utf8PathBuf> {
+    create_temp(name, |path| fs::create_dir(path))
+}
+
+fn create_temp(name: &str, create: impl Fn(&Utf8Path) -> io::Result<()>) -> Result<Utf8PathBuf> {
+    let dir = Utf8PathBuf::from_path_buf(temp_dir())
+        .map_err(|path| anyhow!("`{}` is not UTF-8", path.display()))?;
+    let mut i = 0;
+    loop {
+        let path = dir.join(format!("binary-source-{}-{i}-{name}", process::id()));
+        match create(&path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => i += 1,
+            Err(err) => return Err(err).with_context(|| format!("Failed to create `{path}`")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, StructOpt)]
+pub struct Config {
+    /// `cargo` Path to Cargo.toml
+    #[structopt(long, value_name("PATH"))]
+    pub manifest_path: Option<PathBuf>,
+
+    /// Output filename [default: main.rs]
+    #[structopt(long, short, value_name("PATH"))]
+    pub output: Option<PathBuf>,
+
+    /// Bundle every bin target of the selected packages into `--output-dir`
+    #[structopt(long, conflicts_with_all(&["bin", "input", "fit"]))]
+    pub all_bins: bool,
+
+    /// Output directory with `--all-bins`
+    #[structopt(long, value_name("DIR"), default_value = ".")]
+    pub output_dir: PathBuf,
+
+    /// Output filename with `--all-bins`, `{package}`, `{bin}` and `{ext}` are replaced
+    #[structopt(long, value_name("PATTERN"), default_value = "{bin}.{ext}")]
+    pub output_pattern: String,
+
+    /// Name of the package in the workspace
+    #[structopt(long, short, value_name("SPEC"))]
+    pub package: Option<String>,
+
+    /// Name of the bin target to compile
+    #[structopt(long, value_name("NAME"))]
+    pub bin: Option<String>,
+
+    /// Name of the example target to compile
+    #[structopt(long, value_name("NAME"), conflicts_with_all(&["bin", "all-bins"]))]
+    pub example: Option<String>,
+
+    /// Embed a prebuilt executable instead of compiling with `cargo`
+    #[structopt(
+        long,
+        value_name("PATH"),
+        conflicts_with_all(&["manifest-path", "package", "bin", "example"])
+    )]
+    pub input: Option<PathBuf>,
+
+    /// Source code to embed with `--input`
+    #[structopt(long, value_name("PATH"), requires("input"))]
+    pub source: Option<PathBuf>,
+
+    /// target [default: x86_64-unknown-linux-gnu]
+    #[structopt(long, value_name("TRIPLE"))]
+    pub target: Option<String>,
+
+    /// Preset of the target, CPU, language and size limit for an online judge
+    #[structopt(long, value_name("NAME"))]
+    pub judge: Option<String>,
+
+    /// File of the `--judge` presets [default: ~/.config/binary-source/judges.toml]
+    #[structopt(long, value_name("PATH"))]
+    pub judge_file: Option<PathBuf>,
+
+    /// Use `cross` to compile
+    #[structopt(long)]
+    pub use_cross: bool,
+
+    /// If false, panic_abort
+    #[structopt(long)]
+    pub panic_unwind: bool,
+
+    /// Do not add opt-level="s"
+    #[structopt(long)]
+    pub no_opt_size: bool,
+
+    /// Space or comma separated list of features to activate
+    #[structopt(long, value_name("FEATURES"), number_of_values(1))]
+    pub features: Vec<String>,
+
+    /// Activate all available features
+    #[structopt(long)]
+    pub all_features: bool,
+
+    /// Do not activate the `default` feature
+    #[structopt(long)]
+    pub no_default_features: bool,
+
+    /// Flags appended to the rustflags of `cargo`, e.g. `-C target-cpu=native`
+    #[structopt(
+        long,
+        value_name("FLAGS"),
+        number_of_values(1),
+        allow_hyphen_values(true)
+    )]
+    pub rustflags: Vec<String>,
+
+    /// `rustup` toolchain to build with [default: nightly if installed]
+    #[structopt(long, value_name("NAME"))]
+    pub toolchain: Option<String>,
+
+    /// Do not rebuild std with `-Zbuild-std`, which works without nightly
+    #[structopt(long)]
+    pub no_build_std: bool,
+
+    /// `cargo` profile to build with [default: release]
+    #[structopt(long, value_name("NAME"))]
+    pub profile: Option<String>,
+
+    /// Respect the profile settings in the manifest instead of overriding them for size
+    #[structopt(long)]
+    pub keep_profile: bool,
+
+    /// Additional `profile.<NAME>.<KEY>` setting, where VALUE is in TOML
+    #[structopt(long, value_name("KEY=VALUE"), number_of_values(1))]
+    pub profile_override: Vec<String>,
+
+    /// Do no use upx unless available
+    #[structopt(long)]
+    pub no_upx: bool,
+
+    /// Output language [Rust|Python|Cpp] [default: Rust]
+    #[structopt(long)]
+    pub language: Option<Language>,
+
+    /// How the runner executes the binary [TempFile|Memfd|Cache] [default: TempFile]
+    #[structopt(long, value_name("MODE"))]
+    pub exec_mode: Option<ExecMode>,
+
+    /// Replace the runner process with the binary by exec() on Unix instead of waiting for it
+    #[structopt(long)]
+    pub use_exec: bool,
+
+    /// Make the runner check the SHA-256 of the decoded binary before executing it
+    #[structopt(long)]
+    pub check_hash: bool,
+
+    /// In-source compression of the embedded binary [None|Lz4] [default: None]
+    #[structopt(long, value_name("NAME"))]
+    pub compression: Option<Compression>,
+
+    /// Text encoding of the embedded binary [Base64|Base85|Base91] [default: Base64]
+    #[structopt(long, value_name("NAME"))]
+    pub encoding: Option<Encoding>,
+
+    /// Size limit of the bundled code, e.g. `512KiB`
+    #[structopt(long, value_name("BYTES"))]
+    pub max_size: Option<ByteSize>,
+
+    /// Try other build, compression and encoding options until the code fits in `--max-size`
+    #[structopt(long, requires("max-size"))]
+    pub fit: bool,
+
+    /// Run the generated code and the binary on the same input, and fail if their stdout or exit
+    /// code differ
+    #[structopt(long)]
+    pub verify: bool,
+
+    /// Standard input for `--verify`, one case per file [default: empty]
+    #[structopt(long, value_name("PATH"), number_of_values(1), requires("verify"))]
+    pub verify_input: Vec<PathBuf>,
+
+    /// Edition to compile the generated Rust code with for `--verify` [default: 2021]
+    #[structopt(long, value_name("YEAR"), requires("verify"))]
+    pub verify_edition: Option<String>,
+
+    /// Rebundle into `--output` whenever the sources or the manifest change
+    #[structopt(long, conflicts_with("all-bins"))]
+    pub watch: bool,
+
+    #[structopt(subcommand)]
+    pub subcommand: Option<Subcommand>,
+
+    /// Arguments passed to `cargo build`
+    #[structopt(last = true, value_name("CARGO_ARGS"))]
+    pub cargo_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, StructOpt)]
+pub enum Subcommand {
+    /// Writes out the executable and the source code embedded in generated code
+    Extract(Extract),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    Rust,
+    Python,
+    Cpp,
+}
+
+impl FromStr for Language {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "rust" => Self::Rust,
+            "python" => Self::Python,
+            "cpp" | "c++" => Self::Cpp,
+            _ => Err("Could not parse Language")?,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExecMode {
+    #[default]
+    TempFile,
+    Memfd,
+    /// Keeps the binary in the temp directory, and reuses it while its size and hash match
+    Cache,
+}
+
+impl FromStr for ExecMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "tempfile" => Self::TempFile,
+            "memfd" => Self::Memfd,
+            "cache" => Self::Cache,
+            _ => Err("Could not parse ExecMode")?,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+}
+
+impl FromStr for Compression {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "lz4" => Self::Lz4,
+            _ => Err("Could not parse Compression")?,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Base64,
+    Base85,
+    Base91,
+}
+
+impl FromStr for Encoding {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "base64" => Self::Base64,
+            "base85" => Self::Base85,
+            "base91" => Self::Base91,
+            _ => Err("Could not parse Encoding")?,
+        })
+    }
+}
+
+/// Templates of the generated code for one [`Language`]
+struct Runner {
+    template: &'static str,
+    exec_tempfile: &'static str,
+    exec_memfd: &'static str,
+    exec_cache: &'static str,
+    /// Fails on a binary whose hash differs from the embedded one, for `--check-hash`
+    check_hash: &'static str,
+    /// Runs the cached binary before decoding, for [`ExecMode::Cache`]
+    cache_lookup: &'static str,
+    /// Defines `h`, which returns the SHA-256 of a byte string in upper hex
+    sha256: &'static str,
+    decompress_lz4: &'static str,
+    decode_base64: &'static str,
+    decode_base85: &'static str,
+    decode_base91: &'static str,
+    /// Replaces the helper that runs the binary with one that execs it, `None` for C++, which
+    /// always execs
+    exec_replace: Option<&'static str>,
+}
+
+macro_rules! runner {
+    ($ext:literal) => {
+        Runner {
+            template: include_str!(concat!("../data/binary_runner.", $ext, ".txt")),
+            exec_tempfile: include_str!(concat!("../data/exec_tempfile.", $ext, ".txt")),
+            exec_memfd: include_str!(concat!("../data/exec_memfd.", $ext, ".txt")),
+            exec_cache: include_str!(concat!("../data/exec_cache.", $ext, ".txt")),
+            check_hash: include_str!(concat!("../data/check_hash.", $ext, ".txt")),
+            cache_lookup: include_str!(concat!("../data/cache_lookup.", $ext, ".txt")),
+            sha256: include_str!(concat!("../data/sha256.", $ext, ".txt")),
+            decompress_lz4: include_str!(concat!("../data/decompress_lz4.", $ext, ".txt")),
+            decode_base64: include_str!(concat!("../data/decode_base64.", $ext, ".txt")),
+            decode_base85: include_str!(concat!("../data/decode_base85.", $ext, ".txt")),
+            decode_base91: include_str!(concat!("../data/decode_base91.", $ext, ".txt")),
+            exec_replace: None,
+        }
+    };
+}
+
+impl Language {
+    /// File extension of the generated code
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Language::Rust => "rs",
+            Language::Python => "py",
+            Language::Cpp => "cpp",
+        }
+    }
+
+    fn runner(&self) -> Runner {
+        match self {
+            Language::Rust => Runner {
+                exec_replace: Some(include_str!("../data/exec_replace.rs.txt")),
+                ..runner!("rs")
+            },
+            Language::Python => Runner {
+                exec_replace: Some(include_str!("../data/exec_replace.py.txt")),
+                ..runner!("py")
+            },
+            Language::Cpp => runner!("cpp"),
+        }
+    }
+
+    /// Detects code generated by this tool from the parts of the template around the source
+    /// code, and returns its language and the embedded source code
+    pub fn split_bundle(code: &str) -> Option<(Self, &str)> {
+        [Self::Rust, Self::Python, Self::Cpp]
+            .into_iter()
+            .find_map(|language| {
+                let (header, rest) = language.runner().template.split_once("{{SOURCE_CODE}}")?;
+                // the line closing the source code block and the code up to the next placeholder,
+                // as the header alone also starts ordinary sources, e.g. a Python docstring
+                let footer = &rest[..rest.find("{{")?];
+                let source = code.strip_prefix(header)?;
+                Some((language, &source[..source.rfind(footer)?]))
+            })
+    }
+}
+
+/// Copies the artifact of `ctx` to `binary-source/<PACKAGE>/` next to it, where the build of a
+/// bin of the same name in another package does not overwrite it
+fn keep_artifact(ctx: &mut Ctx<'_>) -> Result<()> {
+    let (Some(dir), Some(file_name)) = (ctx.binary_path.parent(), ctx.binary_path.file_name())
+    else {
+        return Ok(());
+    };
+    if dir.ends_with(Utf8Path::new("binary-source").join(ctx.package_name)) {
+        return Ok(());
+    }
+    let dir = dir.join("binary-source").join(ctx.package_name);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(file_name);
+    fs::copy(&ctx.binary_path, &path)
+        .with_context(|| format!("Failed to copy `{}`", ctx.binary_path))?;
+    ctx.binary_path = path;
+    Ok(())
+}
+
+/// Target resolved from the `cargo` metadata or `--input`
+#[derive(Debug, Clone)]
+pub struct Ctx<'a> {
+    /// Package of the target, `None` with `--input`
+    pub package: Option<&'a Package>,
+    /// Name of the package, or the file name with `--input`
+    pub package_name: &'a str,
+    pub bin_name: &'a str,
+    pub compile_dir: &'a Utf8Path,
+    pub src_path: Option<&'a Utf8Path>,
+    /// Whether the target is an example rather than a bin
+    pub is_example: bool,
+    /// Path of the executable, taken from the artifact messages by [`Config::compile`]
+    pub binary_path: Utf8PathBuf,
+}
+
+/// Row of the size table of `--all-bins`
+struct SizeRow<'a> {
+    package_name: &'a str,
+    bin_name: &'a str,
+    built: ByteSize,
+    /// `None` without upx
+    compressed: Option<ByteSize>,
+    bundled: ByteSize,
+    output: PathBuf,
+    max_size: Option<ByteSize>,
+}
+
+impl Ctx<'_> {
+    /// Whether `cargo` writes the artifacts of `self` and `other` to the same path
+    fn collides_with(&self, other: &Self) -> bool {
+        self.package_name != other.package_name
+            && self.bin_name == other.bin_name
+            && self.is_example == other.is_example
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_iter(["binary-source"])
+    }
+}
+
+impl Config {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Applies the `--judge` preset to the options that are not given
+    pub fn resolve(mut self) -> Result<Self> {
+        if let Some(name) = self.judge.take() {
+            let judge = Judge::load(&name, self.judge_file.as_deref())?;
+            self.target = self.target.or(judge.target.clone());
+            self.language = self.language.or(judge.language);
+            self.max_size = self.max_size.or(judge.max_size);
+            // the given flags come later to take precedence
+            self.rustflags.splice(0..0, judge.rustflags());
+        }
+        Ok(self)
+    }
+
+    pub fn target(&self) -> &str {
+        self.target.as_deref().unwrap_or("x86_64-unknown-linux-gnu")
+    }
+
+    pub fn language(&self) -> Language {
+        self.language.unwrap_or_default()
+    }
+
+    pub fn output(&self) -> &Path {
+        self.output.as_deref().unwrap_or(Path::new("main.rs"))
+    }
+
+    pub fn profile(&self) -> &str {
+        self.profile.as_deref().unwrap_or("release")
+    }
+
+    pub fn verify_edition(&self) -> &str {
+        self.verify_edition.as_deref().unwrap_or("2021")
+    }
+
+    pub fn exec_mode(&self) -> ExecMode {
+        self.exec_mode.unwrap_or_default()
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression.unwrap_or_default()
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding.unwrap_or_default()
+    }
+
+    /// Runs `cargo metadata` for the manifest
+    pub fn metadata(&self) -> Result<Metadata> {
+        let cwd = current_dir().with_context(|| "Failed to get CWD")?;
+        let mut cmd = MetadataCommand::new();
+        if let Some(manifest_path) = &self.manifest_path {
+            cmd.manifest_path(manifest_path);
+        }
+        Ok(cmd.current_dir(cwd).exec()?)
+    }
+
+    /// Lists the bin targets, or the example with `--example`, of `--package`, the current
+    /// package, or every workspace member
+    fn bins<'a>(&self, metadata: &'a Metadata) -> Result<Vec<(&'a Package, &'a Target)>> {
+        let members = metadata.workspace_packages();
+        let packages = match &self.package {
+            Some(name) => {
+                let packages: Vec<_> = members
+                    .iter()
+                    .copied()
+                    .filter(|p| &p.name == name)
+                    .collect();
+                ensure!(
+                    !packages.is_empty(),
+                    "Failed to find package `{name}` in the workspace members: {}",
+                    members
+                        .iter()
+                        .map(|p| p.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                packages
+            }
+            // as with `cargo`, the package of the current directory, or every member at the
+            // root of a virtual workspace
+            None => match metadata.root_package() {
+                Some(package) => vec![package],
+                None => members,
+            },
+        };
+        Ok(packages
+            .iter()
+            .flat_map(|&package| {
+                package
+                    .targets
+                    .iter()
+                    .filter(|t| match &self.example {
+                        Some(example) => t.is_example() && &t.name == example,
+                        None => t.is_bin() && self.bin.as_ref().is_none_or(|b| b == &t.name),
+                    })
+                    .map(move |bin| (package, bin))
+            })
+            .collect())
+    }
+
+    fn bin_ctx<'a>(
+        &self,
+        metadata: &'a Metadata,
+        package: &'a Package,
+        bin: &'a Target,
+    ) -> Ctx<'a> {
+        let profile_dir = match self.profile() {
+            "dev" | "test" => "debug",
+            "bench" => "release",
+            profile => profile,
+        };
+        let mut binary_path = metadata
+            .target_directory
+            .join(self.target())
+            .join(profile_dir);
+        if bin.is_example() {
+            binary_path.push("examples");
+        }
+        binary_path.push(&bin.name);
+        Ctx {
+            package: Some(package),
+            package_name: &package.name,
+            bin_name: &bin.name,
+            compile_dir: package
+                .manifest_path
+                .parent()
+                .expect("`manifest_path` should end with \"Cargo.toml\""),
+            src_path: Some(&bin.src_path),
+            is_example: bin.is_example(),
+            binary_path,
+        }
+    }
+
+    /// Resolves the bin target to compile from the workspace members
+    pub fn ctx<'a>(&self, metadata: &'a Metadata) -> Result<Ctx<'a>> {
+        let bins = self.bins(metadata)?;
+        let default_runs: Vec<_> = bins
+            .iter()
+            .filter(|(package, bin)| package.default_run.as_ref() == Some(&bin.name))
+            .collect();
+        let (package, bin) = match (&bins[..], &default_runs[..]) {
+            ([], _) => match &self.example {
+                Some(example) => bail!("Failed to find example `{example}`"),
+                None => bail!("Failed to find bin"),
+            },
+            ([bin], _) => *bin,
+            (_, [bin]) if self.bin.is_none() => **bin,
+            (bins, _) => bail!(
+                "Multiple targets found, select one of:\n{}",
+                bins.iter()
+                    .map(|(package, bin)| format!(
+                        "  --package {} --{} {}",
+                        package.name,
+                        if bin.is_example() { "example" } else { "bin" },
+                        bin.name
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        };
+        Ok(self.bin_ctx(metadata, package, bin))
+    }
+
+    /// Resolves every bin target of the selected workspace members
+    pub fn all_ctxs<'a>(&self, metadata: &'a Metadata) -> Result<Vec<Ctx<'a>>> {
+        let bins = self.bins(metadata)?;
+        ensure!(!bins.is_empty(), "Failed to find bin");
+        Ok(bins
+            .into_iter()
+            .map(|(package, bin)| self.bin_ctx(metadata, package, bin))
+            .collect())
+    }
+
+    /// Resolves the executable given by `--input`, which is copied to a new temporary file that
+    /// is removed after the code is generated
+    pub fn input_ctx(&self) -> Result<Ctx<'_>> {
+        let input = utf8_path(
+            self.input
+                .as_deref()
+                .with_context(|| "`--input` is not given")?,
+        )?;
+        let bin_name = input
+            .file_name()
+            .with_context(|| format!("`{input}` is not a file"))?;
+        let src_path = self.source.as_deref().map(utf8_path).transpose()?;
+        if let Some(src_path) = src_path {
+            ensure!(src_path.is_file(), "Failed to find source `{src_path}`");
+        }
+        let binary_path = create_temp_file(bin_name)?;
+        Ok(Ctx {
+            package: None,
+            package_name: bin_name,
+            bin_name,
+            compile_dir: input.parent().unwrap_or(Utf8Path::new(".")),
+            src_path,
+            is_example: false,
+            binary_path,
+        })
+    }
+
+    fn resolve_ctx<'a>(&'a self, metadata: Option<&'a Metadata>) -> Result<Ctx<'a>> {
+        match metadata {
+            Some(metadata) => self.ctx(metadata),
+            None => self.input_ctx(),
+        }
+    }
+
+    /// `self` with the project defaults for `ctx` and then the `--judge` preset applied
+    pub fn with_project(&self, metadata: Option<&Metadata>, ctx: &Ctx<'_>) -> Result<Self> {
+        let mut config = self.clone();
+        if let (Some(metadata), Some(package)) = (metadata, ctx.package) {
+            Project::load(metadata, package, ctx.bin_name, ctx.is_example)?.apply(&mut config);
+        }
+        config.resolve()
+    }
+
+    fn is_windows(&self) -> bool {
+        self.target().split('-').nth(2) == Some("windows")
+    }
+
+    /// Refuses to build a previous bundle, a C++ runner for Windows, or to write the bundle over
+    /// the source of `ctx`
+    fn check_output(&self, ctx: &Ctx<'_>, output: &Path) -> Result<()> {
+        ensure!(
+            !(self.is_windows() && self.language() == Language::Cpp),
+            "The C++ runner needs POSIX `execv`, so it does not compile for `{}`. \
+             Use `--language rust` or `--language python`",
+            self.target()
+        );
+        let Some(src_path) = ctx.src_path else {
+            return Ok(());
+        };
+        let src = fs::read_to_string(src_path).unwrap_or_default();
+        if Language::split_bundle(&src).is_some() {
+            ensure!(
+                self.input.is_some(),
+                "`{src_path}` was generated by binary-source, so its runner would be built instead \
+                 of the solution. Restore the source of `{}`, which is kept at the top of the file",
+                ctx.bin_name
+            );
+            // the source is taken from the bundle, so nothing is lost by overwriting it
+            return Ok(());
+        }
+        if let (Ok(output), Ok(src_path)) = (fs::canonicalize(output), src_path.canonicalize()) {
+            ensure!(
+                output != src_path,
+                "`{}` is the source of `{}`, which would be replaced by the bundle. \
+                 Write it to another path with `--output`",
+                output.display(),
+                ctx.bin_name
+            );
+        }
+        Ok(())
+    }
+
+    /// `self` without the options that do not change `cargo build`
+    fn build_options(&self) -> Self {
+        Self {
+            output: None,
+            no_upx: false,
+            language: None,
+            exec_mode: None,
+            use_exec: false,
+            check_hash: false,
+            compression: None,
+            encoding: None,
+            max_size: None,
+            ..self.clone()
+        }
+    }
+
+    /// `profile.<NAME>.*` settings passed to `cargo`
+    fn profile_settings(&self) -> Vec<String> {
+        let mut settings = vec![];
+        if !self.keep_profile {
+            if !self.panic_unwind {
+                settings.push("panic=\"abort\"".to_string());
+            }
+            if !self.no_opt_size {
+                settings.push("opt-level=\"s\"".to_string());
+            }
+            settings.extend(["codegen-units=1", "lto=true", "strip=true"].map(String::from));
+        }
+        settings.extend(self.profile_override.iter().cloned());
+        settings
+    }
+
+    /// Toolchain to build with, and whether std is rebuilt with `-Zbuild-std`
+    fn toolchain(&self) -> Result<(Option<String>, bool)> {
+        let toolchain = match &self.toolchain {
+            Some(toolchain) => {
+                ensure!(
+                    rustc(Some(toolchain), &["--version"]).is_some(),
+                    "Failed to find toolchain `{toolchain}`"
+                );
+                Some(toolchain.clone())
+            }
+            None => rustc(Some("nightly"), &["--version"]).map(|_| "nightly".to_string()),
+        };
+        if self.keep_profile || self.panic_unwind || self.no_build_std {
+            return Ok((toolchain, false));
+        }
+        let toolchain_ref = toolchain.as_deref();
+        let nightly = rustc(toolchain_ref, &["--version"])
+            .is_some_and(|version| version.contains("nightly") || version.contains("-dev"));
+        let rust_src = rustc(toolchain_ref, &["--print", "sysroot"]).is_some_and(|sysroot| {
+            Path::new(sysroot.trim())
+                .join("lib/rustlib/src/rust/library")
+                .is_dir()
+        });
+        if !nightly || !rust_src {
+            eprintln!(
+                "warning: std is not rebuilt because `-Zbuild-std` needs {}. \
+                 The precompiled std keeps its unwinding and formatting machinery, \
+                 which usually makes the binary noticeably larger.",
+                if nightly {
+                    "the `rust-src` component"
+                } else {
+                    "a nightly toolchain"
+                }
+            );
+        }
+        Ok((toolchain, nightly && rust_src))
+    }
+
+    /// Builds the binary, or copies it from `--input`
+    pub fn compile(&self, ctx: &mut Ctx<'_>) -> Result<()> {
+        self.compile_all(slice::from_mut(ctx))
+    }
+
+    /// Builds the binaries, and takes their paths from the artifact messages
+    ///
+    /// `cargo` writes bins of the same name in different packages to the same path, so they are
+    /// built in separate `cargo build` calls and their artifacts are copied out in between.
+    pub fn compile_all(&self, ctxs: &mut [Ctx<'_>]) -> Result<()> {
+        if let Some(input) = &self.input {
+            for ctx in ctxs.iter() {
+                fs::copy(input, &ctx.binary_path)
+                    .with_context(|| format!("Failed to copy `{}`", input.display()))?;
+            }
+            return Ok(());
+        }
+        let (toolchain, build_std) = self.toolchain()?;
+        let mut rounds: Vec<Vec<usize>> = vec![];
+        for (i, ctx) in ctxs.iter().enumerate() {
+            match rounds
+                .iter_mut()
+                .find(|round| round.iter().all(|&j| !ctxs[j].collides_with(ctx)))
+            {
+                Some(round) => round.push(i),
+                None => rounds.push(vec![i]),
+            }
+        }
+        if rounds.len() <= 1 {
+            return self.cargo_build(toolchain.as_deref(), build_std, ctxs);
+        }
+        for round in rounds {
+            let mut built: Vec<_> = round.iter().map(|&i| ctxs[i].clone()).collect();
+            self.cargo_build(toolchain.as_deref(), build_std, &mut built)?;
+            for (i, ctx) in round.into_iter().zip(built) {
+                ctxs[i].binary_path = ctx.binary_path;
+                if ctxs.iter().any(|other| other.collides_with(&ctxs[i])) {
+                    keep_artifact(&mut ctxs[i])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the binaries with a single `cargo build`
+    fn cargo_build(
+        &self,
+        toolchain: Option<&str>,
+        build_std: bool,
+        ctxs: &mut [Ctx<'_>],
+    ) -> Result<()> {
+        let Some(first) = ctxs.first() else {
+            return Ok(());
+        };
+        let mut cmd = self.cargo_command(toolchain, build_std, ctxs, first.compile_dir, |name| {
+            env::var(name).ok()
+        });
+        let mut child = cmd.stdout(Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().expect("stdout should be piped");
+        let mut found = vec![false; ctxs.len()];
+        for message in Message::parse_stream(BufReader::new(stdout)) {
+            match message? {
+                Message::CompilerMessage(msg) => {
+                    if let Some(rendered) = &msg.message.rendered {
+                        eprint!("{rendered}");
+                    }
+                }
+                Message::CompilerArtifact(artifact) => {
+                    let Some(executable) = artifact.executable else {
+                        continue;
+                    };
+                    for (ctx, found) in ctxs.iter_mut().zip(&mut found) {
+                        if ctx.src_path == Some(&artifact.target.src_path)
+                            && ctx.is_example == artifact.target.is_example()
+                        {
+                            // `cross` reports the path inside its container
+                            if executable.exists() {
+                                ctx.binary_path = executable.clone();
+                            } else {
+                                eprintln!(
+                                    "`{executable}` does not exist, using `{}`",
+                                    ctx.binary_path
+                                );
+                            }
+                            *found = true;
+                        }
+                    }
+                }
+                Message::TextLine(line) => println!("{line}"),
+                _ => {}
+            }
+        }
+        let status = child.wait()?;
+        ensure!(status.success(), "Build failed");
+        for (ctx, found) in ctxs.iter().zip(found) {
+            ensure!(found, "Failed to find the artifact of `{}`", ctx.bin_name);
+        }
+        Ok(())
+    }
+
+    /// `cargo build` command for `ctxs` run in `dir`, with the environment variables given by
+    /// `var`
+    fn cargo_command(
+        &self,
+        toolchain: Option<&str>,
+        build_std: bool,
+        ctxs: &[Ctx<'_>],
+        dir: &Utf8Path,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Command {
+        let mut cmd = Command::new(if self.use_cross { "cross" } else { "cargo" });
+        if let Some(toolchain) = toolchain {
+            cmd.arg(format!("+{toolchain}"));
+        }
+        cmd.arg("build")
+            .arg("--message-format=json")
+            .arg(format!("--target={}", self.target()));
+        if build_std {
+            cmd.arg("-Zbuild-std=std,panic_abort")
+                .arg("-Zbuild-std-features=panic_immediate_abort");
+        }
+        for setting in self.profile_settings() {
+            cmd.arg(format!("--config=profile.{}.{setting}", self.profile()));
+        }
+        cmd.arg(format!("--profile={}", self.profile()));
+        if !self.features.is_empty() {
+            cmd.arg("--features").arg(self.features.join(","));
+        }
+        if self.all_features {
+            cmd.arg("--all-features");
+        }
+        if self.no_default_features {
+            cmd.arg("--no-default-features");
+        }
+        rustflags::apply(
+            &mut cmd,
+            &self.rustflags,
+            self.target(),
+            dir.as_std_path(),
+            var,
+        );
+        cmd.args(&self.cargo_args);
+        let mut packages: Vec<_> = ctxs.iter().map(|ctx| ctx.package_name).collect();
+        packages.sort_unstable();
+        packages.dedup();
+        for package in packages {
+            cmd.arg("--package").arg(package);
+        }
+        for ctx in ctxs.iter() {
+            cmd.arg(if ctx.is_example { "--example" } else { "--bin" })
+                .arg(ctx.bin_name);
+        }
+        cmd.current_dir(dir);
+        cmd
+    }
+
+    /// Packs the binary with upx in place
+    pub fn compress(&self, ctx: &Ctx<'_>) -> Result<()> {
+        let status = Command::new("upx")
+            .args(["--best", "--lzma", "-qq"])
+            .arg(&ctx.binary_path)
+            .status()
+            .with_context(|| "Failed to run upx")?;
+        ensure!(status.success(), "upx failed");
+        Ok(())
+    }
+
+    fn template(&self) -> String {
+        let runner = self.language().runner();
+        // memfd falls through to the temp file path when it is unavailable
+        let execute = match self.exec_mode() {
+            ExecMode::TempFile => runner.exec_tempfile.to_string(),
+            ExecMode::Memfd => format!("{}{}", runner.exec_memfd, runner.exec_tempfile),
+            ExecMode::Cache => runner.exec_cache.to_string(),
+        };
+        let execute = match runner.exec_replace.filter(|_| self.use_exec) {
+            Some(exec_replace) => format!("{exec_replace}{execute}"),
+            None => execute,
+        };
+        let decompress = match self.compression() {
+            Compression::None => "",
+            Compression::Lz4 => runner.decompress_lz4,
+        };
+        let decompress = if self.check_hash {
+            format!("{decompress}{}", runner.check_hash)
+        } else {
+            decompress.to_string()
+        };
+        let decode = match self.encoding() {
+            Encoding::Base64 => runner.decode_base64,
+            Encoding::Base85 => runner.decode_base85,
+            Encoding::Base91 => runner.decode_base91,
+        };
+        // a cache hit skips decoding
+        let decode = match self.exec_mode() {
+            ExecMode::Cache => format!("{}{decode}", runner.cache_lookup),
+            _ => decode.to_string(),
+        };
+        let decode = if self.exec_mode() == ExecMode::Cache || self.check_hash {
+            format!("{}{decode}", runner.sha256)
+        } else {
+            decode
+        };
+        runner
+            .template
+            .replacen("{{DECODE}}", &decode, 1)
+            .replacen("{{DECOMPRESS}}", &decompress, 1)
+            .replacen("{{EXECUTE}}", &execute, 1)
+    }
+
+    /// Generates the code with the binary embedded
+    pub fn embed(&self, ctx: &Ctx<'_>) -> Result<String> {
+        let template = self.template();
+        let bin = fs::read(&ctx.binary_path)?;
+        let hash = HEXUPPER.encode(&sha2::Sha256::digest(&bin));
+        let len = bin.len();
+        let payload = match self.compression() {
+            Compression::None => bin,
+            Compression::Lz4 => {
+                let payload = lz4::compress(&bin);
+                let size = ByteSize::b(payload.len() as u64);
+                println!("LZ4 compressed binary size: {size}");
+                payload
+            }
+        };
+        let encoded = match self.encoding() {
+            Encoding::Base64 => match self.language() {
+                Language::Rust | Language::Cpp => BASE64_NOPAD.encode(&payload),
+                Language::Python => BASE64.encode(&payload),
+            },
+            Encoding::Base85 => encoding::base85(&payload),
+            Encoding::Base91 => encoding::base91(&payload),
+        };
+        let size = ByteSize::b(encoded.len() as u64);
+        println!("Encoded binary size ({:?}): {size}", self.encoding());
+        let ext = if self.is_windows() { ".exe" } else { "" };
+        let name = format!("bin{hash}{ext}");
+        let source_code = ctx
+            .src_path
+            .and_then(|src_path| fs::read_to_string(src_path).ok())
+            .unwrap_or("SOURCE CODE NOT FOUND".to_string());
+        // a previous bundle given as `--source` is not nested
+        let source_code =
+            Language::split_bundle(&source_code).map_or(source_code.as_str(), |(_, src)| src);
+
+        // the payload and the source code may contain the other placeholders, so they come last
+        let code = template
+            .replace("{{NAME}}", &name)
+            .replace("{{HASH}}", &hash)
+            .replace("{{SIZE}}", &len.to_string())
+            .replacen("{{BINARY}}", &encoded, 1)
+            .replacen("{{SOURCE_CODE}}", source_code.trim_end(), 1);
+        Ok(code)
+    }
+
+    fn check_size(&self, code: &str) -> Result<()> {
+        if let Some(max_size) = self.max_size {
+            let size = ByteSize::b(code.len() as u64);
+            ensure!(
+                size <= max_size,
+                "Bundled code size {size} exceeds the limit of {max_size}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Options that can be changed by `--fit`, as command line flags
+    fn flags(&self) -> String {
+        let mut flags = vec![];
+        if self.panic_unwind {
+            flags.push("--panic-unwind".to_string());
+        }
+        if self.no_opt_size {
+            flags.push("--no-opt-size".to_string());
+        }
+        if self.no_upx {
+            flags.push("--no-upx".to_string());
+        }
+        flags.push(format!("--compression {:?}", self.compression()));
+        flags.push(format!("--encoding {:?}", self.encoding()));
+        flags.join(" ")
+    }
+
+    /// Number of options that differ from `other`
+    fn distance(&self, other: &Self) -> usize {
+        [
+            self.panic_unwind != other.panic_unwind,
+            self.no_opt_size != other.no_opt_size,
+            self.no_upx != other.no_upx,
+            self.compression() != other.compression(),
+            self.encoding() != other.encoding(),
+        ]
+        .into_iter()
+        .filter(|&d| d)
+        .count()
+    }
+
+    /// Build options to try with `--fit`, closest to `self` first
+    fn build_candidates(&self) -> Vec<Self> {
+        if self.input.is_some() || self.keep_profile {
+            return vec![self.clone()];
+        }
+        let mut candidates = vec![];
+        for panic_unwind in [self.panic_unwind, !self.panic_unwind] {
+            for no_opt_size in [self.no_opt_size, !self.no_opt_size] {
+                candidates.push(Self {
+                    panic_unwind,
+                    no_opt_size,
+                    ..self.clone()
+                });
+            }
+        }
+        candidates.sort_by_key(|c| self.distance(c));
+        candidates
+    }
+
+    /// Compression and encoding options to try with `--fit`, closest to `self` first
+    fn embed_candidates(&self) -> Vec<Self> {
+        let mut candidates = vec![];
+        for no_upx in [self.no_upx, !self.no_upx] {
+            for compression in [Compression::None, Compression::Lz4] {
+                for encoding in [Encoding::Base64, Encoding::Base85, Encoding::Base91] {
+                    candidates.push(Self {
+                        no_upx,
+                        compression: Some(compression),
+                        encoding: Some(encoding),
+                        ..self.clone()
+                    });
+                }
+            }
+        }
+        candidates.sort_by_key(|c| self.distance(c));
+        candidates
+    }
+
+    fn fit_binary_source(&self, ctx: &mut Ctx<'_>, max_size: ByteSize) -> Result<String> {
+        for build in self.build_candidates() {
+            if let Err(err) = build.compile(ctx) {
+                println!("Skipped `{}`: {err}", build.flags());
+                continue;
+            }
+            let size = ByteSize::b(get_file_size(&ctx.binary_path)?);
+            println!("Built binary size: {size}");
+
+            // keep the uncompressed binary for the `--no-upx` candidates
+            let packed = Ctx {
+                binary_path: ctx.binary_path.with_extension("upx"),
+                ..ctx.clone()
+            };
+            fs::copy(&ctx.binary_path, &packed.binary_path)?;
+            let upx = build.compress(&packed);
+            if let Err(err) = &upx {
+                println!("Skipped upx: {err}");
+            }
+
+            for candidate in build.embed_candidates() {
+                if !candidate.no_upx && upx.is_err() {
+                    continue;
+                }
+                let code = candidate.embed(if candidate.no_upx { ctx } else { &packed })?;
+                let size = ByteSize::b(code.len() as u64);
+                println!("Bundled code size with `{}`: {size}", candidate.flags());
+                if size <= max_size {
+                    println!("Fits in {max_size} with `{}`", candidate.flags());
+                    return Ok(code);
+                }
+            }
+        }
+        bail!("No configuration fits in {max_size}")
+    }
+
+    /// Runs the generated code and the binary of `ctx` on each `--verify-input`, and compares
+    /// their stdout and exit codes
+    pub fn verify(&self, ctx: &Ctx<'_>, code: &str) -> Result<()> {
+        let dir = create_temp_dir(&format!("verify-{}", ctx.bin_name))?;
+        let result = self.verify_in(ctx, code, &dir);
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    /// Compiles and runs the generated code in `dir` for [`Config::verify`]
+    fn verify_in(&self, ctx: &Ctx<'_>, code: &str, dir: &Utf8Path) -> Result<()> {
+        let language = self.language();
+        let src = dir.join(format!("main.{}", language.extension()));
+        fs::write(&src, code)?;
+        let exe = dir.join("main");
+        let compiler = match language {
+            Language::Rust => Some((
+                "rustc",
+                vec![
+                    format!("--edition={}", self.verify_edition()),
+                    "-O".into(),
+                    "-o".into(),
+                ],
+            )),
+            Language::Python => None,
+            Language::Cpp => Some(("c++", vec!["-std=c++17".into(), "-O2".into(), "-o".into()])),
+        };
+        let runner: Vec<OsString> = match compiler {
+            Some((compiler, args)) => {
+                let status = Command::new(compiler)
+                    .args(args)
+                    .arg(&exe)
+                    .arg(&src)
+                    .status()
+                    .with_context(|| format!("Failed to run `{compiler}`"))?;
+                ensure!(
+                    status.success(),
+                    "Failed to compile the generated code with `{compiler}`"
+                );
+                vec![exe.into()]
+            }
+            None => vec!["python3".into(), src.into()],
+        };
+        let binary = vec![ctx.binary_path.clone().into()];
+
+        let cases = match &self.verify_input[..] {
+            [] => vec![None],
+            inputs => inputs.iter().map(Some).collect(),
+        };
+        for case in cases {
+            let (name, input) = match case {
+                Some(path) => (
+                    format!("`{}`", path.display()),
+                    fs::read(path)
+                        .with_context(|| format!("Failed to read `{}`", path.display()))?,
+                ),
+                None => ("empty input".to_string(), vec![]),
+            };
+            let (expected_code, expected) = run_with_input(&binary, &input)?;
+            let (code, actual) = run_with_input(&runner, &input)?;
+            let status = |code: Option<i32>| code.map_or("a signal".to_string(), |c| c.to_string());
+            ensure!(
+                code == expected_code,
+                "Verification failed on {name}: the generated code exited with {}, \
+                 but the binary with {}",
+                status(code),
+                status(expected_code)
+            );
+            if let Some(pos) = actual.iter().zip(&expected).position(|(a, b)| a != b) {
+                bail!("Verification failed on {name}: stdout differs at byte {pos}");
+            }
+            ensure!(
+                actual.len() == expected.len(),
+                "Verification failed on {name}: stdout is {} bytes, but {} bytes with the binary",
+                actual.len(),
+                expected.len()
+            );
+            println!(
+                "Verified on {name}: same stdout and exit code {}",
+                status(code)
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs the whole pipeline, and returns `self` with the project defaults applied together
+    /// with the generated code
+    fn gen(&self) -> Result<(Self, String)> {
+        let metadata = match self.input {
+            Some(_) => None,
+            None => Some(self.metadata()?),
+        };
+        let mut ctx = self.resolve_ctx(metadata.as_ref())?;
+        let result = self.gen_ctx(metadata.as_ref(), &mut ctx);
+        if self.input.is_some() {
+            // the copy of `--input`, and the one packed by `--fit`
+            for path in [
+                ctx.binary_path.clone(),
+                ctx.binary_path.with_extension("upx"),
+            ] {
+                let _ = fs::remove_file(path);
+            }
+        }
+        result
+    }
+
+    /// Runs the pipeline for `ctx`
+    fn gen_ctx(&self, metadata: Option<&Metadata>, ctx: &mut Ctx<'_>) -> Result<(Self, String)> {
+        let config = self.with_project(metadata, ctx)?;
+        config.check_output(ctx, config.output())?;
+        let code = if let (true, Some(max_size)) = (config.fit, config.max_size) {
+            config.fit_binary_source(ctx, max_size)?
+        } else {
+            config.compile(ctx)?;
+            let size = ByteSize::b(get_file_size(&ctx.binary_path)?);
+            println!("Built binary size: {size}");
+
+            if !config.no_upx {
+                config.compress(ctx)?;
+                let size = ByteSize::b(get_file_size(&ctx.binary_path)?);
+                println!("Compressed binary size: {size}");
+            }
+
+            let code = config.embed(ctx)?;
+            let size = ByteSize::b(code.len() as u64);
+            println!("Bundled code size: {size}");
+            config.check_size(&code)?;
+            code
+        };
+        if config.verify {
+            config.verify(ctx, &code)?;
+        }
+
+        Ok((config, code))
+    }
+
+    /// Runs the whole pipeline and returns the generated code
+    pub fn gen_binary_source(&self) -> Result<String> {
+        Ok(self.gen()?.1)
+    }
+
+    /// Runs the whole pipeline and writes the generated code to `output`, or every bin with
+    /// `all_bins`
+    pub fn run(&self) -> Result<()> {
+        if let Some(Subcommand::Extract(extract)) = &self.subcommand {
+            return extract.run();
+        }
+        if self.all_bins {
+            return self.bundle_all_bins();
+        }
+        if self.watch {
+            return self.watch();
+        }
+        let (config, code) = self.gen()?;
+        config.save_binary(code.as_bytes())
+    }
+
+    /// Files and directories the generated code depends on
+    fn watch_roots(&self) -> Result<Vec<PathBuf>> {
+        if let Some(input) = &self.input {
+            return Ok(self.source.iter().chain([input]).cloned().collect());
+        }
+        let metadata = self.metadata()?;
+        let ctx = self.ctx(&metadata)?;
+        let mut roots = vec![ctx.compile_dir.into()];
+        for name in ["Cargo.toml", project::FILE_NAME] {
+            roots.push(metadata.workspace_root.join(name).into());
+        }
+        Ok(roots)
+    }
+
+    /// Writes the generated code to `output`, and again on every change of the sources
+    pub fn watch(&self) -> Result<()> {
+        let mut roots = vec![];
+        loop {
+            match self.watch_roots() {
+                Ok(found) => roots = found,
+                Err(err) => eprintln!("Error: {err:?}"),
+            }
+            // sources saved during the build trigger the next cycle
+            let mut before = watch::snapshot(&roots);
+            let output = self.gen().and_then(|(config, code)| {
+                config.save_binary(code.as_bytes())?;
+                Ok(fs::canonicalize(config.output())?)
+            });
+            let output = match output {
+                Ok(output) => Some(output),
+                Err(err) => {
+                    eprintln!("Error: {err:?}");
+                    None
+                }
+            };
+            if let Some(output) = &output {
+                before.remove(output);
+            }
+            println!("Watching {} files for changes", before.len());
+            loop {
+                thread::sleep(watch::POLL_INTERVAL);
+                let mut after = watch::snapshot(&roots);
+                if let Some(output) = &output {
+                    after.remove(output);
+                }
+                if after != before {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Writes the generated code to `output`
+    pub fn save_binary(&self, src: &[u8]) -> Result<()> {
+        write_code(self.output(), src)
+    }
+
+    /// Output path of `ctx` with `--all-bins`
+    pub fn output_path(&self, ctx: &Ctx<'_>) -> PathBuf {
+        self.output_dir.join(
+            self.output_pattern
+                .replace("{package}", ctx.package_name)
+                .replace("{bin}", ctx.bin_name)
+                .replace("{ext}", self.language().extension()),
+        )
+    }
+
+    /// Bundles every bin target of the selected packages into `output_dir`
+    pub fn bundle_all_bins(&self) -> Result<()> {
+        let metadata = self.metadata()?;
+        let mut bins = vec![];
+        for ctx in self.all_ctxs(&metadata)? {
+            let config = self.with_project(Some(&metadata), &ctx)?;
+            config.check_output(&ctx, &config.output_path(&ctx))?;
+            bins.push((config, ctx));
+        }
+        for (i, (config, ctx)) in bins.iter().enumerate() {
+            let output = config.output_path(ctx);
+            if let Some((_, other)) = bins[..i]
+                .iter()
+                .find(|(config, other)| config.output_path(other) == output)
+            {
+                bail!(
+                    "`{}/{}` and `{}/{}` would both be written to `{}`. \
+                     Add `{{package}}` to `--output-pattern`",
+                    other.package_name,
+                    other.bin_name,
+                    ctx.package_name,
+                    ctx.bin_name,
+                    output.display()
+                );
+            }
+        }
+        // bins whose project defaults agree on the build options share a `cargo build`
+        for i in 0..bins.len() {
+            let build = bins[i].0.build_options();
+            if bins[..i]
+                .iter()
+                .any(|(config, _)| config.build_options() == build)
+            {
+                continue;
+            }
+            let same_build = |config: &Self| config.build_options() == build;
+            let mut ctxs: Vec<_> = bins[i..]
+                .iter()
+                .filter(|(config, _)| same_build(config))
+                .map(|(_, ctx)| ctx.clone())
+                .collect();
+            build.compile_all(&mut ctxs)?;
+            let built = bins[i..]
+                .iter_mut()
+                .filter(|(config, _)| same_build(config));
+            for ((_, ctx), compiled) in built.zip(ctxs) {
+                *ctx = compiled;
+            }
+            // the next builds may overwrite the artifacts of the same name
+            for j in i..bins.len() {
+                let ctx = &bins[j].1;
+                if same_build(&bins[j].0) && bins.iter().any(|(_, other)| other.collides_with(ctx))
+                {
+                    keep_artifact(&mut bins[j].1)?;
+                }
+            }
+        }
+        fs::create_dir_all(&self.output_dir)?;
+
+        let mut rows = vec![];
+        for (config, ctx) in &bins {
+            let built = ByteSize::b(get_file_size(&ctx.binary_path)?);
+            let compressed = if config.no_upx {
+                None
+            } else {
+                config.compress(ctx)?;
+                Some(ByteSize::b(get_file_size(&ctx.binary_path)?))
+            };
+            let code = config.embed(ctx)?;
+            if config.verify {
+                config.verify(ctx, &code)?;
+            }
+            let output = config.output_path(ctx);
+            write_code(&output, code.as_bytes())?;
+            let bundled = ByteSize::b(code.len() as u64);
+            rows.push(SizeRow {
+                package_name: ctx.package_name,
+                bin_name: ctx.bin_name,
+                built,
+                compressed,
+                bundled,
+                output,
+                max_size: config.max_size,
+            });
+        }
+
+        println!(
+            "{:<16} {:<16} {:>12} {:>12} {:>12}  Output",
+            "Package", "Bin", "Built", "Compressed", "Bundled"
+        );
+        for row in &rows {
+            let compressed = row
+                .compressed
+                .map_or("-".to_string(), |size| size.to_string());
+            println!(
+                "{:<16} {:<16} {:>12} {compressed:>12} {:>12}  {}",
+                row.package_name,
+                row.bin_name,
+                row.built.to_string(),
+                row.bundled.to_string(),
+                row.output.display()
+            );
+        }
+
+        let over: Vec<_> = rows
+            .iter()
+            .filter_map(|row| {
+                let max_size = row.max_size.filter(|&max_size| row.bundled > max_size)?;
+                Some(format!(
+                    "{}/{} ({} > {max_size})",
+                    row.package_name, row.bin_name, row.bundled
+                ))
+            })
+            .collect();
+        ensure!(
+            over.is_empty(),
+            "Bundled code size exceeds the limit: {}",
+            over.join(", ")
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Binary and source files of a target in the temp directory, removed on drop
+    pub(crate) struct TestTarget {
+        binary_path: Utf8PathBuf,
+        src_path: Option<Utf8PathBuf>,
+    }
+
+    impl TestTarget {
+        pub(crate) fn new(binary: &[u8], source: Option<&str>) -> Self {
+            let binary_path = create_temp_file("test").unwrap();
+            fs::write(&binary_path, binary).unwrap();
+            let src_path = source.map(|source| {
+                let src_path = create_temp_file("test-source").unwrap();
+                fs::write(&src_path, source).unwrap();
+                src_path
+            });
+            Self {
+                binary_path,
+                src_path,
+            }
+        }
+
+        pub(crate) fn ctx(&self) -> Ctx<'_> {
+            Ctx {
+                package: None,
+                package_name: "test",
+                bin_name: "test",
+                compile_dir: Utf8Path::new("."),
+                src_path: self.src_path.as_deref(),
+                is_example: false,
+                binary_path: self.binary_path.clone(),
+            }
+        }
+    }
+
+    impl Drop for TestTarget {
+        fn drop(&mut self) {
+            for path in [Some(&self.binary_path), self.src_path.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    #[test]
+    fn payload_keeps_placeholders() {
+        let text = "{{NAME}}00{{HASH}}00{{SIZE}}00";
+        let bin = encoding::decode_base85(text.as_bytes()).unwrap();
+        assert_eq!(encoding::base85(&bin), text);
+        let target = TestTarget::new(&bin, None);
+        for language in [Language::Rust, Language::Python, Language::Cpp] {
+            let config = Config {
+                language: Some(language),
+                encoding: Some(Encoding::Base85),
+                ..Config::default()
+            };
+            let code = config.embed(&target.ctx()).unwrap();
+            assert!(code.contains(text), "{code}");
+        }
+    }
+
+    /// Arguments and environment variables of the `cargo build` command of `config`, run in a
+    /// directory with `.cargo/config.toml` and the environment variables `env`
+    fn cargo_command(config: &Config, env: &[(&str, &str)]) -> (Vec<String>, Vec<String>) {
+        let dir = create_temp_dir("test-cargo").unwrap();
+        fs::create_dir(dir.join(".cargo")).unwrap();
+        fs::write(
+            dir.join(".cargo/config.toml"),
+            "[build]\nrustflags = [\"--cfg\", \"fromconfig\"]\n",
+        )
+        .unwrap();
+        let target = TestTarget::new(b"", None);
+        let cmd = config.cargo_command(None, false, &[target.ctx()], &dir, |name| {
+            let mut env = env.iter().chain(&[("CARGO_HOME", "/nonexistent")]);
+            env.find(|(key, _)| *key == name)
+                .map(|(_, v)| v.to_string())
+        });
+        let args = cmd
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let envs = cmd
+            .get_envs()
+            .map(|(key, value)| {
+                let value = value.map_or("".into(), |value| value.to_string_lossy());
+                format!("{}={value}", key.to_string_lossy())
+            })
+            .collect();
+        fs::remove_dir_all(dir).unwrap();
+        (args, envs)
+    }
+
+    #[test]
+    fn rustflags_keep_other_sources() {
+        let config = Config {
+            rustflags: vec!["--cfg foo".into()],
+            ..Config::default()
+        };
+        let (args, envs) = cargo_command(&config, &[]);
+        assert!(args.contains(&r#"--config=build.rustflags=["--cfg", "foo"]"#.to_string()));
+        assert!(envs.is_empty(), "{envs:?}");
+
+        // from a build script
+        let (args, envs) = cargo_command(&config, &[("CARGO_ENCODED_RUSTFLAGS", "")]);
+        assert!(!args.iter().any(|arg| arg.contains("rustflags")));
+        assert_eq!(envs, ["CARGO_ENCODED_RUSTFLAGS=--cfg\x1ffoo"]);
+    }
+
+    #[test]
+    fn judge_rustflags_keep_other_sources() {
+        let config = Config {
+            judge: Some("atcoder".into()),
+            judge_file: Some("/nonexistent".into()),
+            ..Config::default()
+        }
+        .resolve()
+        .unwrap();
+        let cpu = r#"--config=build.rustflags=["-C", "target-cpu=x86-64-v3"]"#;
+        let (args, envs) = cargo_command(&config, &[]);
+        assert!(args.contains(&cpu.to_string()), "{args:?}");
+        assert!(envs.is_empty(), "{envs:?}");
+
+        let (_, envs) = cargo_command(&config, &[("CARGO_ENCODED_RUSTFLAGS", "")]);
+        assert_eq!(envs, ["CARGO_ENCODED_RUSTFLAGS=-C\x1ftarget-cpu=x86-64-v3"]);
+    }
+
+    #[test]
+    fn source_with_docstring_is_not_a_bundle() {
+        let source = "\"\"\"\nSolve ABC123 A\n\"\"\"\nimport sys\nprint(sys.stdin.read())\n";
+        assert_eq!(Language::split_bundle(source), None);
+        let target = TestTarget::new(b"", Some(source));
+        for language in [Language::Rust, Language::Python, Language::Cpp] {
+            let config = Config {
+                language: Some(language),
+                ..Config::default()
+            };
+            let code = config.embed(&target.ctx()).unwrap();
+            assert_eq!(
+                Language::split_bundle(&code),
+                Some((language, source.trim_end()))
+            );
+        }
+    }
+
+    #[test]
+    fn python_runner_forwards_argv_and_exit_code() {
+        if Command::new("python3").arg("--version").output().is_err() {
+            eprintln!("skipped: python3 is not found");
+            return;
+        }
+        let target = TestTarget::new(b"#!/bin/sh\nprintf '%s|' \"$@\"\nexit 3\n", None);
+        let dir = create_temp_dir("test-runner").unwrap();
+        let src = dir.join("main.py");
+        for compression in [Compression::None, Compression::Lz4] {
+            for encoding in [Encoding::Base64, Encoding::Base85, Encoding::Base91] {
+                for exec_mode in [ExecMode::TempFile, ExecMode::Memfd, ExecMode::Cache] {
+                    let config = Config {
+                        language: Some(Language::Python),
+                        compression: Some(compression),
+                        encoding: Some(encoding),
+                        exec_mode: Some(exec_mode),
+                        check_hash: true,
+                        ..Config::default()
+                    };
+                    fs::write(&src, config.embed(&target.ctx()).unwrap()).unwrap();
+                    let argv = [
+                        "python3".into(),
+                        src.clone().into(),
+                        "a b".into(),
+                        "c".into(),
+                    ];
+                    let (code, stdout) = run_with_input(&argv, b"").unwrap();
+                    assert_eq!(
+                        (code, String::from_utf8_lossy(&stdout).as_ref()),
+                        (Some(3), "a b|c|"),
+                        "{}",
+                        config.flags()
+                    );
+                }
+            }
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+//! Compressor for the LZ4 block format.
+//!
+//! The output is decoded by the `decompress_lz4.*.txt` fragments in the runners, so only the
+//! plain block format is used: a sequence of `token, literals, offset, match` with the final
+//! sequence carrying literals only.
+
+const MIN_MATCH: usize = 4;
+const WINDOW: usize = 0xffff;
+const HASH_BITS: u32 = 16;
+const MAX_CHAIN: usize = 1024;
+const NONE: usize = usize::MAX;
+
+struct Matcher<'a> {
+    src: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+    inserted: usize,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(src: &'a [u8]) -> Self {
+        Self {
+            src,
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: vec![NONE; src.len()],
+            inserted: 0,
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let v = u32::from_le_bytes(self.src[pos..pos + MIN_MATCH].try_into().unwrap());
+        (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Registers every position before `pos` in the hash chains.
+    fn update(&mut self, pos: usize) {
+        while self.inserted < pos && self.inserted + MIN_MATCH <= self.src.len() {
+            let h = self.hash(self.inserted);
+            self.prev[self.inserted] = self.head[h];
+            self.head[h] = self.inserted;
+            self.inserted += 1;
+        }
+    }
+
+    /// Returns `(length, offset)` of the longest match for `pos` within the window.
+    fn find(&mut self, pos: usize) -> (usize, usize) {
+        self.update(pos);
+        let mut best = (0, 0);
+        let mut cand = self.head[self.hash(pos)];
+        let mut chain = 0;
+        while cand != NONE && pos - cand <= WINDOW && chain < MAX_CHAIN {
+            let len = self.src[cand..]
+                .iter()
+                .zip(&self.src[pos..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len > best.0 {
+                best = (len, pos - cand);
+            }
+            cand = self.prev[cand];
+            chain += 1;
+        }
+        best
+    }
+}
+
+fn write_length(dst: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        dst.push(255);
+        len -= 255;
+    }
+    dst.push(len as u8);
+}
+
+fn write_sequence(dst: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let lit = literals.len();
+    let len = matched.map_or(0, |(len, _)| len - MIN_MATCH);
+    dst.push((lit.min(15) << 4 | len.min(15)) as u8);
+    if lit >= 15 {
+        write_length(dst, lit - 15);
+    }
+    dst.extend_from_slice(literals);
+    if let Some((_, offset)) = matched {
+        dst.extend_from_slice(&(offset as u16).to_le_bytes());
+        if len >= 15 {
+            write_length(dst, len - 15);
+        }
+    }
+}
+
+/// Compresses `src` with hash chains and one step of lazy matching.
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let mut dst = Vec::new();
+    let mut matcher = Matcher::new(src);
+    let (mut anchor, mut pos) = (0, 0);
+    while pos + MIN_MATCH <= src.len() {
+        let (len, offset) = matcher.find(pos);
+        if len < MIN_MATCH || pos + 1 + MIN_MATCH <= src.len() && matcher.find(pos + 1).0 > len + 1
+        {
+            pos += 1;
+            continue;
+        }
+        write_sequence(&mut dst, &src[anchor..pos], Some((len, offset)));
+        pos += len;
+        anchor = pos;
+    }
+    write_sequence(&mut dst, &src[anchor..], None);
+    dst
+}
+
+fn read_length(src: &[u8], pos: &mut usize, mut len: usize) -> Option<usize> {
+    if len == 15 {
+        loop {
+            let c = *src.get(*pos)?;
+            *pos += 1;
+            len += c as usize;
+            if c < 255 {
+                break;
+            }
+        }
+    }
+    Some(len)
+}
+
+/// Decompresses the output of [`compress`], or returns `None` if `src` is malformed.
+pub fn decompress(src: &[u8]) -> Option<Vec<u8>> {
+    let mut dst = Vec::with_capacity(src.len() * 2);
+    let mut pos = 0;
+    loop {
+        let token = *src.get(pos)? as usize;
+        pos += 1;
+        let lit = read_length(src, &mut pos, token >> 4)?;
+        dst.extend_from_slice(src.get(pos..pos + lit)?);
+        pos += lit;
+        if pos >= src.len() {
+            return Some(dst);
+        }
+        let offset = u16::from_le_bytes(src.get(pos..pos + 2)?.try_into().unwrap()) as usize;
+        pos += 2;
+        let len = read_length(src, &mut pos, token & 15)? + MIN_MATCH;
+        if offset == 0 || offset > dst.len() {
+            return None;
+        }
+        for _ in 0..len {
+            dst.push(dst[dst.len() - offset]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(src: &[u8]) {
+        let compressed = compress(src);
+        assert_eq!(decompress(&compressed).as_deref(), Some(src));
+    }
+
+    /// Deterministic bytes that do not compress
+    fn noise(len: usize, mut seed: u64) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_inputs() {
+        for len in 0..32 {
+            round_trip(&noise(len, 1));
+            round_trip(&vec![7; len]);
+        }
+    }
+
+    #[test]
+    fn long_zero_runs() {
+        for len in [15 + 4, 255 + 15 + 4, 1 << 16, 1 << 20] {
+            let src = vec![0; len];
+            let compressed = compress(&src);
+            assert!(compressed.len() < len / 200 + 16);
+            round_trip(&src);
+        }
+    }
+
+    #[test]
+    fn overlapping_matches() {
+        for period in 1..8 {
+            let src: Vec<u8> = (0..1000).map(|i| (i % period) as u8).collect();
+            round_trip(&src);
+        }
+        let mut src = noise(100, 2);
+        src.extend(b"abcabcabcabcabcabcabcabc");
+        src.extend(noise(100, 3));
+        round_trip(&src);
+    }
+
+    #[test]
+    fn offsets_near_window() {
+        let block = noise(1024, 4);
+        for offset in [WINDOW - 1, WINDOW, WINDOW + 1] {
+            let mut src = block.clone();
+            src.extend(noise(offset - block.len(), 5));
+            src.extend(&block);
+            let compressed = compress(&src);
+            // the repeated block is only found within the window
+            assert_eq!(compressed.len() < src.len(), offset <= WINDOW);
+            round_trip(&src);
+        }
+    }
+
+    #[test]
+    fn mixed_data() {
+        let mut src = vec![];
+        for i in 0..200 {
+            src.extend(noise(i * 7 % 300, i as u64 + 1));
+            src.extend(vec![i as u8; i % 40]);
+            let start = src.len() / 3;
+            src.extend_from_within(start..start + i % 50);
+        }
+        round_trip(&src);
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert_eq!(decompress(&[]), None);
+        // literal length beyond the input
+        assert_eq!(decompress(&[0x50, 1, 2]), None);
+        // offset before the start of the output
+        assert_eq!(decompress(&[0x10, 1, 2, 0, 0x00]), None);
+        assert_eq!(decompress(&[0x10, 1, 0, 0, 0x00]), None);
+    }
+}
@@ -0,0 +1,183 @@
+//! Project defaults from `[package.metadata.binary-source]` and `binary-source.toml`.
+//!
+//! Both take the same keys as the long command line options. `[bin.<NAME>]` and
+//! `[example.<NAME>]` tables override them for one target, and the command line overrides all.
+
+use std::{collections::BTreeMap, fs, mem, path::PathBuf};
+
+use anyhow::{Context as _, Result};
+use bytesize::ByteSize;
+use cargo_metadata::{camino::Utf8Path, Metadata, Package};
+use serde::Deserialize;
+
+use crate::{judge::from_str, Compression, Config, Encoding, ExecMode, Language};
+
+/// File looked up in the package directory, then in the workspace root
+pub const FILE_NAME: &str = "binary-source.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Project {
+    /// Relative to the directory of `Cargo.toml` or `binary-source.toml`
+    pub output: Option<PathBuf>,
+    pub target: Option<String>,
+    pub judge: Option<String>,
+    pub use_cross: Option<bool>,
+    pub panic_unwind: Option<bool>,
+    pub no_opt_size: Option<bool>,
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub all_features: Option<bool>,
+    pub no_default_features: Option<bool>,
+    #[serde(default)]
+    pub rustflags: Vec<String>,
+    pub toolchain: Option<String>,
+    pub no_build_std: Option<bool>,
+    pub profile: Option<String>,
+    pub keep_profile: Option<bool>,
+    #[serde(default)]
+    pub profile_override: Vec<String>,
+    pub no_upx: Option<bool>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub language: Option<Language>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub exec_mode: Option<ExecMode>,
+    pub use_exec: Option<bool>,
+    pub check_hash: Option<bool>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub compression: Option<Compression>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub encoding: Option<Encoding>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub max_size: Option<ByteSize>,
+    /// Overrides for bin targets
+    #[serde(default)]
+    pub bin: BTreeMap<String, Project>,
+    /// Overrides for example targets
+    #[serde(default)]
+    pub example: BTreeMap<String, Project>,
+}
+
+impl Project {
+    /// Reads the defaults of `package` for the target `name`. `binary-source.toml` takes
+    /// precedence over the manifest.
+    pub fn load(
+        metadata: &Metadata,
+        package: &Package,
+        name: &str,
+        is_example: bool,
+    ) -> Result<Self> {
+        let package_dir = package
+            .manifest_path
+            .parent()
+            .expect("`manifest_path` should end with \"Cargo.toml\"");
+        let mut project = match package.metadata.get("binary-source") {
+            Some(value) => Self::deserialize(value)
+                .with_context(|| {
+                    format!(
+                        "Failed to parse `[package.metadata.binary-source]` in `{}`",
+                        package.manifest_path
+                    )
+                })?
+                .relative_to(package_dir)
+                .select(name, is_example),
+            None => Self::default(),
+        };
+        for dir in [package_dir, &metadata.workspace_root] {
+            let path = dir.join(FILE_NAME);
+            if path.is_file() {
+                let src = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read `{path}`"))?;
+                let file: Self =
+                    toml::from_str(&src).with_context(|| format!("Failed to parse `{path}`"))?;
+                project = file.relative_to(dir).select(name, is_example).or(project);
+                break;
+            }
+        }
+        Ok(project)
+    }
+
+    fn relative_to(mut self, dir: &Utf8Path) -> Self {
+        self.output = self.output.map(|output| dir.as_std_path().join(output));
+        for overrides in [&mut self.bin, &mut self.example] {
+            *overrides = mem::take(overrides)
+                .into_iter()
+                .map(|(name, project)| (name, project.relative_to(dir)))
+                .collect();
+        }
+        self
+    }
+
+    /// Applies the overrides for the target `name`
+    fn select(mut self, name: &str, is_example: bool) -> Self {
+        let overrides = if is_example {
+            &mut self.example
+        } else {
+            &mut self.bin
+        };
+        match overrides.remove(name) {
+            Some(overrides) => overrides.or(self),
+            None => self,
+        }
+    }
+
+    /// Fills the options not set in `self` from `lower`
+    fn or(self, lower: Self) -> Self {
+        // the flags of `self` come later to take precedence
+        let concat = |lower: Vec<String>, upper: Vec<String>| [lower, upper].concat();
+        Self {
+            output: self.output.or(lower.output),
+            target: self.target.or(lower.target),
+            judge: self.judge.or(lower.judge),
+            use_cross: self.use_cross.or(lower.use_cross),
+            panic_unwind: self.panic_unwind.or(lower.panic_unwind),
+            no_opt_size: self.no_opt_size.or(lower.no_opt_size),
+            features: concat(lower.features, self.features),
+            all_features: self.all_features.or(lower.all_features),
+            no_default_features: self.no_default_features.or(lower.no_default_features),
+            rustflags: concat(lower.rustflags, self.rustflags),
+            toolchain: self.toolchain.or(lower.toolchain),
+            no_build_std: self.no_build_std.or(lower.no_build_std),
+            profile: self.profile.or(lower.profile),
+            keep_profile: self.keep_profile.or(lower.keep_profile),
+            profile_override: concat(lower.profile_override, self.profile_override),
+            no_upx: self.no_upx.or(lower.no_upx),
+            language: self.language.or(lower.language),
+            exec_mode: self.exec_mode.or(lower.exec_mode),
+            use_exec: self.use_exec.or(lower.use_exec),
+            check_hash: self.check_hash.or(lower.check_hash),
+            compression: self.compression.or(lower.compression),
+            encoding: self.encoding.or(lower.encoding),
+            max_size: self.max_size.or(lower.max_size),
+            bin: BTreeMap::new(),
+            example: BTreeMap::new(),
+        }
+    }
+
+    /// Fills the options not given in `config`. Flags can only be turned on.
+    pub fn apply(self, config: &mut Config) {
+        config.output = config.output.take().or(self.output);
+        config.target = config.target.take().or(self.target);
+        config.judge = config.judge.take().or(self.judge);
+        config.use_cross |= self.use_cross.unwrap_or_default();
+        config.panic_unwind |= self.panic_unwind.unwrap_or_default();
+        config.no_opt_size |= self.no_opt_size.unwrap_or_default();
+        config.features.splice(0..0, self.features);
+        config.all_features |= self.all_features.unwrap_or_default();
+        config.no_default_features |= self.no_default_features.unwrap_or_default();
+        config.rustflags.splice(0..0, self.rustflags);
+        config.toolchain = config.toolchain.take().or(self.toolchain);
+        config.no_build_std |= self.no_build_std.unwrap_or_default();
+        config.profile = config.profile.take().or(self.profile);
+        config.keep_profile |= self.keep_profile.unwrap_or_default();
+        config.profile_override.splice(0..0, self.profile_override);
+        config.no_upx |= self.no_upx.unwrap_or_default();
+        config.language = config.language.or(self.language);
+        config.exec_mode = config.exec_mode.or(self.exec_mode);
+        config.use_exec |= self.use_exec.unwrap_or_default();
+        config.check_hash |= self.check_hash.unwrap_or_default();
+        config.compression = config.compression.or(self.compression);
+        config.encoding = config.encoding.or(self.encoding);
+        config.max_size = config.max_size.or(self.max_size);
+    }
+}
@@ -0,0 +1,212 @@
+//! Passing `--rustflags` to `cargo` without dropping the flags from other sources.
+//!
+//! `cargo` takes the flags from the first source that is set among `CARGO_ENCODED_RUSTFLAGS`,
+//! `RUSTFLAGS`, `target.<triple>.rustflags` together with `target.<cfg>.rustflags`, and
+//! `build.rustflags`. Setting `RUSTFLAGS` alone would hide the flags of `.cargo/config.toml`, so
+//! the flags are appended to the source that `cargo` reads.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Whether a `target.<triple>.rustflags` or `target.<cfg>.rustflags` is configured in `path`
+fn has_target_rustflags(path: &Path, target: &str) -> bool {
+    let Some(table) = fs::read_to_string(path)
+        .ok()
+        .and_then(|src| src.parse::<toml::Table>().ok())
+    else {
+        return false;
+    };
+    let Some(targets) = table.get("target").and_then(|t| t.as_table()) else {
+        return false;
+    };
+    targets.iter().any(|(key, value)| {
+        (key == target || key.starts_with("cfg(")) && value.get("rustflags").is_some()
+    })
+}
+
+/// Config files that `cargo` reads when run in `dir`
+fn config_files(dir: &Path, var: &impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let cargo_home = var("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| Path::new(&home).join(".cargo")));
+    dir.ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home)
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+        .collect()
+}
+
+/// Appends `flags` to the rustflags of `cmd`, which runs `cargo build --target=<target>` in
+/// `dir` with the environment variables given by `var`
+pub fn apply(
+    cmd: &mut Command,
+    flags: &[String],
+    target: &str,
+    dir: &Path,
+    var: impl Fn(&str) -> Option<String>,
+) {
+    let flags: Vec<_> = flags.iter().flat_map(|f| f.split_whitespace()).collect();
+    if flags.is_empty() {
+        return;
+    }
+    if let Some(encoded) = var("CARGO_ENCODED_RUSTFLAGS") {
+        let encoded: Vec<_> = encoded
+            .split('\x1f')
+            .filter(|f| !f.is_empty())
+            .chain(flags)
+            .collect();
+        cmd.env("CARGO_ENCODED_RUSTFLAGS", encoded.join("\x1f"));
+    } else if let Some(rustflags) = var("RUSTFLAGS") {
+        let rustflags: Vec<_> = rustflags.split_whitespace().chain(flags).collect();
+        cmd.env("RUSTFLAGS", rustflags.join(" "));
+    } else {
+        let target_var = format!(
+            "CARGO_TARGET_{}_RUSTFLAGS",
+            target.to_uppercase().replace(['-', '.'], "_")
+        );
+        // the `target` tables take precedence over `build.rustflags` and are joined together
+        let key = if var(&target_var).is_some()
+            || config_files(dir, &var)
+                .iter()
+                .any(|path| has_target_rustflags(path, target))
+        {
+            format!("target.{target}.rustflags")
+        } else {
+            "build.rustflags".to_string()
+        };
+        // `cargo` appends arrays given by `--config` to those of the config files
+        let flags = toml::Value::from(flags.iter().map(|f| f.to_string()).collect::<Vec<_>>());
+        cmd.arg(format!("--config={key}={flags}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, ffi::OsStr};
+
+    use super::*;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    fn run(env: &[(&str, &str)], dir: &Path, flags: &[&str]) -> Command {
+        let env: HashMap<_, _> = env.iter().copied().collect();
+        let flags: Vec<_> = flags.iter().map(|f| f.to_string()).collect();
+        let mut cmd = Command::new("cargo");
+        apply(&mut cmd, &flags, TARGET, dir, |name| {
+            env.get(name).map(|v| v.to_string())
+        });
+        cmd
+    }
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn envs(cmd: &Command) -> Vec<(&OsStr, Option<&OsStr>)> {
+        cmd.get_envs().collect()
+    }
+
+    #[test]
+    fn extends_encoded_rustflags() {
+        let dir = Path::new("/nonexistent");
+        let cmd = run(
+            &[
+                ("CARGO_ENCODED_RUSTFLAGS", "--cfg\x1fa"),
+                ("RUSTFLAGS", "-g"),
+            ],
+            dir,
+            &["--cfg foo"],
+        );
+        assert_eq!(
+            envs(&cmd),
+            [(
+                OsStr::new("CARGO_ENCODED_RUSTFLAGS"),
+                Some(OsStr::new("--cfg\x1fa\x1f--cfg\x1ffoo"))
+            )]
+        );
+        assert!(args(&cmd).is_empty());
+        // set but empty, as in build scripts
+        let cmd = run(&[("CARGO_ENCODED_RUSTFLAGS", "")], dir, &["--cfg foo"]);
+        assert_eq!(
+            envs(&cmd),
+            [(
+                OsStr::new("CARGO_ENCODED_RUSTFLAGS"),
+                Some(OsStr::new("--cfg\x1ffoo"))
+            )]
+        );
+    }
+
+    #[test]
+    fn extends_rustflags() {
+        let cmd = run(
+            &[("RUSTFLAGS", "-C debuginfo=1")],
+            Path::new("/nonexistent"),
+            &["--cfg foo", "-C target-cpu=native"],
+        );
+        assert_eq!(
+            envs(&cmd),
+            [(
+                OsStr::new("RUSTFLAGS"),
+                Some(OsStr::new("-C debuginfo=1 --cfg foo -C target-cpu=native"))
+            )]
+        );
+        assert!(args(&cmd).is_empty());
+    }
+
+    #[test]
+    fn appends_to_config() {
+        let root = crate::create_temp_dir("test-rustflags").unwrap();
+        let dir = root.join("a/b");
+        fs::create_dir_all(root.join(".cargo")).unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        let home = [("CARGO_HOME", "/nonexistent")];
+
+        let cmd = run(&home, dir.as_std_path(), &["--cfg foo"]);
+        assert!(envs(&cmd).is_empty());
+        assert_eq!(args(&cmd), [r#"--config=build.rustflags=["--cfg", "foo"]"#]);
+
+        let config = root.join(".cargo/config.toml");
+        fs::write(
+            &config,
+            "[build]\nrustflags = [\"--cfg\", \"fromconfig\"]\n",
+        )
+        .unwrap();
+        let cmd = run(&home, dir.as_std_path(), &["--cfg foo"]);
+        assert_eq!(args(&cmd), [r#"--config=build.rustflags=["--cfg", "foo"]"#]);
+
+        fs::write(
+            &config,
+            format!("[target.{TARGET}]\nrustflags = [\"--cfg\", \"fromconfig\"]\n"),
+        )
+        .unwrap();
+        let cmd = run(&home, dir.as_std_path(), &["--cfg foo"]);
+        assert_eq!(
+            args(&cmd),
+            [format!(
+                r#"--config=target.{TARGET}.rustflags=["--cfg", "foo"]"#
+            )]
+        );
+
+        fs::remove_file(&config).unwrap();
+        let cmd = run(
+            &[
+                ("CARGO_HOME", "/nonexistent"),
+                ("CARGO_TARGET_X86_64_UNKNOWN_LINUX_GNU_RUSTFLAGS", "-g"),
+            ],
+            dir.as_std_path(),
+            &["--cfg foo"],
+        );
+        assert_eq!(
+            args(&cmd),
+            [format!(
+                r#"--config=target.{TARGET}.rustflags=["--cfg", "foo"]"#
+            )]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
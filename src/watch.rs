@@ -0,0 +1,54 @@
+//! Polling of the sources for `--watch`.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Interval between two scans of the sources
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Modification times of the files in `roots`, keyed by the canonical path. Directories are
+/// scanned for `.rs` and `.toml` files, skipping `target` and hidden directories.
+pub fn snapshot(roots: &[PathBuf]) -> BTreeMap<PathBuf, SystemTime> {
+    let mut files = BTreeMap::new();
+    for root in roots {
+        if root.is_dir() {
+            visit(root, &mut files);
+        } else {
+            insert(root, &mut files);
+        }
+    }
+    files
+}
+
+fn visit(dir: &Path, files: &mut BTreeMap<PathBuf, SystemTime>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name != "target" && !name.starts_with('.') {
+                visit(&path, files);
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "rs" || ext == "toml")
+        {
+            insert(&path, files);
+        }
+    }
+}
+
+fn insert(path: &Path, files: &mut BTreeMap<PathBuf, SystemTime>) {
+    // deleted files are left out so that they count as changed
+    if let (Ok(path), Ok(modified)) = (
+        fs::canonicalize(path),
+        fs::metadata(path).and_then(|m| m.modified()),
+    ) {
+        files.insert(path, modified);
+    }
+}